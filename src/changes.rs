@@ -0,0 +1,57 @@
+use std::path::{Component, Path, PathBuf};
+
+pub const RESERVED_FILENAMES: [&str; 2] = ["README.md", "readme.md"];
+pub const RESERVED_DIRNAMES: [&str; 4] = [".git", "assets", "templates", "themes"];
+pub const COLLECTION_CONFIG_FILENAME: &str = "_collection.toml";
+
+/// What a changed input path affects, used by watch to rebuild only the affected outputs.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    /// a file in `assets/`, relative to it
+    Asset(PathBuf),
+    /// a template by name i.e. `post` for `templates/post.hbs`
+    Template(String),
+    /// a markdown file at the root
+    Page(String),
+    /// a markdown file in a collection as (collection, filename)
+    Entity(String, String),
+    /// a non markdown path at the root which may be a collection directory
+    Root(String),
+    /// a change that cannot be scoped
+    Full,
+    Ignored,
+}
+
+/// Classifies a path relative to the input directory.
+pub fn classify_change(path: &Path) -> Change {
+    let components = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    // hidden files include .git/ and the swap files of most editors
+    if components.iter().any(|c| c.starts_with('.')) {
+        return Change::Ignored;
+    }
+    match components.as_slice() {
+        [] => Change::Full,
+        [name] if name == "stoic.toml" => Change::Full,
+        [dir] if RESERVED_DIRNAMES.contains(&dir.as_str()) => Change::Full,
+        [dir, rest @ ..] if dir == "assets" => Change::Asset(rest.iter().collect()),
+        [dir, ..] if dir == "themes" => Change::Full,
+        [dir, name] if dir == "templates" => match name.strip_suffix(".hbs") {
+            Some(template_name) => Change::Template(template_name.to_string()),
+            None => Change::Ignored,
+        },
+        [name] if RESERVED_FILENAMES.contains(&name.as_str()) => Change::Ignored,
+        [name] if name.ends_with(".md") => Change::Page(name.clone()),
+        [name] => Change::Root(name.clone()),
+        [_, name] if name == COLLECTION_CONFIG_FILENAME => Change::Full,
+        [collection, name] if name.ends_with(".md") => {
+            Change::Entity(collection.clone(), name.clone())
+        }
+        _ => Change::Ignored,
+    }
+}
@@ -38,10 +38,16 @@ impl<'a> Component for ConsoleView<'a> {
             lines.push(Line::from_iter(vec![Span::new_unstyled("Error:")?]));
 
             for (i, e) in report.chain().enumerate() {
-                lines.push(Line::from_iter(vec![
-                    Span::new_unstyled(format!("    {i}: "))?,
-                    Span::new_colored(&e.to_string(), Color::DarkRed)?,
-                ]));
+                // spans cannot contain newlines so multi-line errors are split
+                let e_str = e.to_string();
+                let prefix = format!("    {i}: ");
+                let indent = " ".repeat(prefix.len());
+                for (j, l) in e_str.lines().enumerate() {
+                    lines.push(Line::from_iter(vec![
+                        Span::new_unstyled(if j == 0 { &prefix } else { &indent })?,
+                        Span::new_colored(l, Color::DarkRed)?,
+                    ]));
+                }
             }
         }
 
@@ -94,6 +100,20 @@ impl ConsoleHandle {
         Ok(())
     }
 
+    /// Prints a warning above the console so that it is not cleared by later renders.
+    pub fn log_warning(&mut self, warning: &str) -> Result<()> {
+        let mut lines = Lines::new();
+        for l in warning.lines() {
+            lines.push(Line::from_iter(vec![Span::new_colored(
+                &format!("Warning: {l}"),
+                Color::DarkYellow,
+            )
+            .map_err(|e| eyre!(e))?]));
+        }
+        self.console.emit(lines);
+        self.render()
+    }
+
     pub fn log(&mut self, message: &str) -> Result<()> {
         self.state.message = Some(message.to_string());
         self.render()?;
@@ -16,3 +16,25 @@ pub struct RenderError {
     pub path: PathBuf,
     pub template_name: String,
 }
+
+#[derive(Error, Debug)]
+#[error("Failed to parse {path:?}")]
+pub struct ConfigError {
+    pub path: PathBuf,
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid metadata in {path:?} at line {line}, column {column}: {message}")]
+pub struct MetadataError {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+#[derive(Error, Debug)]
+#[error("Broken link {link:?} in {path:?}")]
+pub struct LinkError {
+    pub path: PathBuf,
+    pub link: String,
+}
@@ -0,0 +1,129 @@
+use crate::links::absolute_links;
+use crate::types::{Entity, SiteConfig};
+use crate::utils::escape_xml;
+use chrono::{NaiveDate, NaiveTime, TimeZone, Utc};
+
+fn to_utc(date_str: &str) -> chrono::DateTime<Utc> {
+    let date = NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
+        .unwrap_or(Utc::now().date_naive())
+        .and_time(NaiveTime::MIN);
+    Utc.from_utc_datetime(&date)
+}
+
+/// Collections can be sorted in any order so the newest entity is not necessarily the first.
+fn newest_date(entities: &[Entity]) -> Option<chrono::DateTime<Utc>> {
+    entities.iter().map(|e| to_utc(&e.created_at_iso)).max()
+}
+
+pub fn rss_feed(
+    site: &SiteConfig,
+    base_url: &str,
+    collection_name: &str,
+    title: &str,
+    entities: &[Entity],
+) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    let collection_url = format!("{base_url}/{collection_name}/");
+    let title = match &site.title {
+        Some(site_title) => format!("{site_title} - {title}"),
+        None => title.to_string(),
+    };
+    let mut out = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    out.push_str(r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel>"#);
+    out.push_str(&format!("<title>{}</title>", escape_xml(&title)));
+    out.push_str(&format!("<link>{}</link>", escape_xml(&collection_url)));
+    out.push_str(&format!(
+        r#"<atom:link href="{}" rel="self" type="application/rss+xml"/>"#,
+        escape_xml(&format!("{collection_url}feed.xml"))
+    ));
+    out.push_str(&format!(
+        "<description>{}</description>",
+        escape_xml(site.description.as_deref().unwrap_or(&title))
+    ));
+    if let Some(language) = &site.language {
+        out.push_str(&format!("<language>{}</language>", escape_xml(language)));
+    }
+    if let Some(date) = newest_date(entities) {
+        out.push_str(&format!(
+            "<lastBuildDate>{}</lastBuildDate>",
+            date.to_rfc2822()
+        ));
+    }
+    for entity in entities {
+        let link = escape_xml(&format!("{collection_url}{}", entity.filename));
+        out.push_str("<item>");
+        out.push_str(&format!("<title>{}</title>", escape_xml(&entity.title)));
+        out.push_str(&format!("<link>{link}</link>"));
+        out.push_str(&format!(r#"<guid isPermaLink="true">{link}</guid>"#));
+        out.push_str(&format!(
+            "<pubDate>{}</pubDate>",
+            to_utc(&entity.created_at_iso).to_rfc2822()
+        ));
+        out.push_str(&format!(
+            "<description>{}</description>",
+            escape_xml(&absolute_links(&entity.contents, base_url))
+        ));
+        out.push_str("</item>");
+    }
+    out.push_str("</channel></rss>\n");
+    out
+}
+
+pub fn atom_feed(
+    site: &SiteConfig,
+    base_url: &str,
+    collection_name: &str,
+    title: &str,
+    entities: &[Entity],
+) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    let collection_url = format!("{base_url}/{collection_name}/");
+    let title = match &site.title {
+        Some(site_title) => format!("{site_title} - {title}"),
+        None => title.to_string(),
+    };
+    let updated = newest_date(entities).unwrap_or(Utc::now());
+    let mut out = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    out.push_str(r#"<feed xmlns="http://www.w3.org/2005/Atom""#);
+    if let Some(language) = &site.language {
+        out.push_str(&format!(r#" xml:lang="{}""#, escape_xml(language)));
+    }
+    out.push('>');
+    out.push_str(&format!("<title>{}</title>", escape_xml(&title)));
+    out.push_str(&format!(
+        "<id>{}</id>",
+        escape_xml(&format!("{collection_url}atom.xml"))
+    ));
+    out.push_str(&format!(
+        r#"<link href="{}" rel="self"/>"#,
+        escape_xml(&format!("{collection_url}atom.xml"))
+    ));
+    out.push_str(&format!(
+        r#"<link href="{}"/>"#,
+        escape_xml(&collection_url)
+    ));
+    out.push_str(&format!("<updated>{}</updated>", updated.to_rfc3339()));
+    if let Some(author) = &site.author {
+        out.push_str(&format!(
+            "<author><name>{}</name></author>",
+            escape_xml(author)
+        ));
+    }
+    for entity in entities {
+        let link = escape_xml(&format!("{collection_url}{}", entity.filename));
+        let date = to_utc(&entity.created_at_iso).to_rfc3339();
+        out.push_str("<entry>");
+        out.push_str(&format!("<title>{}</title>", escape_xml(&entity.title)));
+        out.push_str(&format!("<id>{link}</id>"));
+        out.push_str(&format!(r#"<link href="{link}"/>"#));
+        out.push_str(&format!("<published>{date}</published>"));
+        out.push_str(&format!("<updated>{date}</updated>"));
+        out.push_str(&format!(
+            r#"<content type="html">{}</content>"#,
+            escape_xml(&absolute_links(&entity.contents, base_url))
+        ));
+        out.push_str("</entry>");
+    }
+    out.push_str("</feed>\n");
+    out
+}
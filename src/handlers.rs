@@ -1,32 +1,55 @@
 use crate::assets::{CSS_STR, JS_STR};
+use crate::changes::{
+    classify_change, Change, COLLECTION_CONFIG_FILENAME, RESERVED_DIRNAMES, RESERVED_FILENAMES,
+};
 use crate::console::ConsoleHandle;
-use crate::errors::{IOError, RenderError};
+use crate::errors::{ConfigError, IOError, LinkError, MetadataError, RenderError};
+use crate::feeds::{atom_feed, rss_feed};
+use crate::helpers::{register_helpers, Asset};
+use crate::highlight::theme_css;
+use crate::links::broken_links;
+use crate::minify::{is_minifiable, minify_asset, minify_html};
+use crate::sitemap::{robots_txt, sitemap, SitemapEntry};
 use crate::templates::TemplateName;
 use crate::types::*;
 use crate::utils::{
-    copy_file, get_entries_in_dir, get_files_in_dir_recursive, md_to_html, read_template,
-    remove_path,
+    check_collection_path, copy_file, entity_filename, fingerprint_path, get_entries_in_dir,
+    get_files_in_dir_recursive, get_preserved_paths, get_stale_paths, md_to_html,
+    neighbour_sources, neighbours, page_path, paginate, preserve_globs, read_collection,
+    read_site_config, read_template, reading_time, remove_if_exists, term_slug, toml_to_json,
+    url_path,
 };
 use chrono::prelude::*;
 use color_eyre::eyre::Context;
-use color_eyre::{eyre::eyre, Result};
-use futures::future::{try_join3, try_join_all};
+use color_eyre::{eyre::eyre, Report, Result};
+use futures::future::{try_join, try_join3, try_join_all};
 use futures::stream::FuturesUnordered;
 use futures::FutureExt;
+use globset::GlobSet;
 use handlebars::Handlebars;
 use heck::ToTitleCase;
-use inquire::Confirm;
+use inquire::{Confirm, Select, Text};
 use notify::{EventKind, RecursiveMode};
 use notify_debouncer_full::new_debouncer;
 use serde_json::json;
+use std::cell::RefCell;
 use std::cmp::Reverse;
-use std::{path::Path, sync::mpsc, time::Duration};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::{env, io, time::Duration};
 use strum::IntoEnumIterator;
-use tokio::fs::{create_dir, metadata, read_to_string, write};
+use tokio::fs::{
+    canonicalize, create_dir, create_dir_all, metadata, read, read_to_string, remove_dir,
+    remove_dir_all, remove_file, rename, write,
+};
 use tokio::net::TcpListener;
+use tokio::process;
 use tower_http::services::ServeDir;
 use tower_livereload::LiveReloadLayer;
 
+const PORT_ATTEMPTS: u16 = 10;
+
 pub async fn run_new(console: &mut ConsoleHandle, root_dir: &Path) -> Result<()> {
     let start = Utc::now();
     let assets_dir = root_dir.join("assets");
@@ -54,6 +77,13 @@ pub async fn run_new(console: &mut ConsoleHandle, root_dir: &Path) -> Result<()>
                 format!("# {}\n", name.to_title_case()),
             ),
             write(root_dir.join("about.md"), "# About\n".to_owned()),
+            write(
+                root_dir.join("stoic.toml"),
+                format!(
+                    "title = \"{}\"\n# set to enable feeds and the sitemap\n# base_url = \"https://example.com\"\nlanguage = \"en\"\n",
+                    name.to_title_case()
+                ),
+            ),
             write(
                 assets_dir.join("style.css"),
                 CSS_STR.to_string().trim_start().to_owned(),
@@ -90,17 +120,130 @@ date = {date}
     Ok(())
 }
 
+pub async fn run_post(
+    console: &mut ConsoleHandle,
+    root_dir: &Path,
+    collection_name: Option<String>,
+    title: Option<String>,
+    should_edit: bool,
+) -> Result<()> {
+    metadata(root_dir)
+        .await
+        .wrap_err(eyre!("\"{}\" does not exist", root_dir.display()))?;
+
+    let collection_name = match collection_name {
+        Some(collection_name) => collection_name,
+        None => {
+            let (_, collection_names) = get_pages_and_collections(root_dir).await?;
+            if collection_names.is_empty() {
+                return Err(eyre!("\"{}\" has no collections", root_dir.display()));
+            }
+            Select::new("Collection:", collection_names).prompt()?
+        }
+    };
+    let collection_dir = root_dir.join(&collection_name);
+    if !metadata(&collection_dir).await.is_ok_and(|m| m.is_dir()) {
+        return Err(eyre!(
+            "Collection \"{}\" does not exist in \"{}\"",
+            collection_name,
+            root_dir.display()
+        ));
+    }
+
+    let title = match title {
+        Some(title) => title,
+        None => Text::new("Title:").prompt()?,
+    };
+    let date = Utc::now().format("%Y-%m-%d").to_string();
+    let filename =
+        entity_filename(&date, &title).ok_or(eyre!("\"{}\" is not a valid title", title))?;
+    let path = collection_dir.join(filename);
+    if metadata(&path).await.is_ok() {
+        return Err(eyre!("{} already exists", path.display()));
+    }
+    write(
+        &path,
+        format!(
+            r"<!--metadata
+date = {date}
+-->
+
+# {title}
+"
+        ),
+    )
+    .await
+    .wrap_err(IOError::Create { path: path.clone() })?;
+    console.log(&format!("Created {}", path.display()))?;
+
+    if should_edit {
+        let editor = env::var("VISUAL")
+            .or(env::var("EDITOR"))
+            .wrap_err("Neither $VISUAL nor $EDITOR is set")?;
+        // the editor may include arguments i.e. "code --wait"
+        let mut args = editor.split_whitespace();
+        let program = args.next().ok_or(eyre!("$EDITOR is empty"))?;
+        let status = process::Command::new(program)
+            .args(args)
+            .arg(&path)
+            .status()
+            .await
+            .wrap_err(eyre!("Failed to run \"{editor}\""))?;
+        if !status.success() {
+            return Err(eyre!("\"{editor}\" exited with {status}"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Fails in strict mode and otherwise records the metadata error of `path` as a warning.
+fn check_metadata(
+    error: Option<MetadataError>,
+    path: &Path,
+    options: &BuildOptions,
+    warnings: &Warnings,
+) -> Result<()> {
+    let Some(error) = error else {
+        return Ok(());
+    };
+    let error = MetadataError {
+        path: path.to_path_buf(),
+        ..error
+    };
+    if options.strict {
+        return Err(error.into());
+    }
+    warnings.borrow_mut().push(error.into());
+    Ok(())
+}
+
 async fn build_page<'a>(
     h: &Handlebars<'a>,
+    site: &SiteConfig,
+    options: &BuildOptions,
     name: String,
     input_dir: &Path,
     output_dir: &Path,
+    warnings: &Warnings,
 ) -> Result<()> {
     let path = input_dir.join(&name);
     let md_str = read_to_string(&path)
         .await
-        .wrap_err(IOError::Read { path })?;
-    let (_, title, contents) = md_to_html(&md_str);
+        .wrap_err(IOError::Read { path: path.clone() })?;
+    let Document {
+        metadata,
+        metadata_error,
+        title,
+        contents,
+        toc,
+        word_count,
+        ..
+    } = md_to_html(&md_str, site.highlight.as_ref(), site.heading_anchors);
+    check_metadata(metadata_error, &path, options, warnings)?;
+    let meta = toml_to_json(&toml::Value::Table(
+        metadata.map(|m| m.extra).unwrap_or_default(),
+    ));
     let out_name = name.replace(".md", ".html");
     let name_no_ext = name.strip_suffix(".md").unwrap_or(&name);
     let template_name = if h.has_template(name_no_ext) {
@@ -112,13 +255,23 @@ async fn build_page<'a>(
         .render(
             template_name,
             &json!(EntityArgs {
+                site,
                 path: &[Breadcrumb {
                     name: &title,
                     link: &out_name,
                 }],
                 title: &title,
                 head_title: &title,
-                contents: &contents
+                contents: &contents,
+                taxonomies: &BTreeMap::new(),
+                draft: false,
+                scheduled: false,
+                meta: &meta,
+                toc: &toc,
+                word_count,
+                reading_time: reading_time(word_count),
+                previous: None,
+                next: None,
             }),
         )
         .wrap_err(RenderError {
@@ -126,7 +279,7 @@ async fn build_page<'a>(
             template_name: template_name.to_string(),
         })?;
 
-    write(output_dir.join(out_name), out)
+    write_html(site, output_dir.join(out_name), out)
         .await
         .wrap_err(IOError::Create {
             path: name.clone().into(),
@@ -134,29 +287,52 @@ async fn build_page<'a>(
     Ok(())
 }
 
-async fn build_entity<'a>(
-    h: &Handlebars<'a>,
+/// Writes a rendered page, minified when enabled.
+async fn write_html(site: &SiteConfig, path: impl AsRef<Path>, html: String) -> io::Result<()> {
+    let html = if site.minify {
+        minify_html(&html)
+    } else {
+        html
+    };
+    write(path, html).await
+}
+
+async fn read_entity(
+    site: &SiteConfig,
+    options: &BuildOptions,
     name: &str,
-    collection_name: &str,
-    breadcrumbs: &'a [Breadcrumb<'a>],
+    collection: &Collection,
     input_dir: &Path,
-    output_dir: &Path,
-) -> Result<Entity> {
+    warnings: &Warnings,
+) -> Result<Option<Entity>> {
     let path = input_dir.join(name);
     let md_str = read_to_string(&path)
         .await
         .wrap_err(IOError::Read { path: path.clone() })?;
-    let (metadata, title, contents) = md_to_html(&md_str);
+    let Document {
+        metadata,
+        metadata_error,
+        title,
+        contents,
+        toc,
+        excerpt,
+        word_count,
+    } = md_to_html(&md_str, site.highlight.as_ref(), site.heading_anchors);
+    check_metadata(metadata_error, &path, options, warnings)?;
     let date_str = metadata
         .as_ref()
         .and_then(|m| m.date)
         .and_then(|dt| dt.date)
         .map(|d| d.to_string())
         .unwrap_or(Utc::now().date_naive().format("%Y-%m-%d").to_string());
-    let created_at = NaiveDate::parse_from_str(date_str.as_str(), "%Y-%m-%d")
-        .unwrap_or(Utc::now().date_naive())
-        .format("%b %d, %Y")
-        .to_string();
+    let date =
+        NaiveDate::parse_from_str(date_str.as_str(), "%Y-%m-%d").unwrap_or(Utc::now().date_naive());
+    let draft = metadata.as_ref().and_then(|m| m.draft).unwrap_or(false);
+    let scheduled = date > Utc::now().date_naive();
+    if (draft && !options.drafts) || (scheduled && !options.future) {
+        return Ok(None);
+    }
+    let created_at = date.format("%b %d, %Y").to_string();
     let shortname = metadata
         .as_ref()
         .and_then(|m| m.shortname.clone())
@@ -170,296 +346,1677 @@ async fn build_entity<'a>(
         .as_ref()
         .and_then(|e| e.head_title.clone())
         .unwrap_or(title.clone());
-    let link = format!("{collection_name}/{slug}");
-    let template_name = collection_name.strip_suffix("s").unwrap_or(collection_name);
+    let link = format!("{}/{slug}", collection.path);
+    let meta = toml_to_json(&toml::Value::Table(
+        metadata
+            .as_ref()
+            .map(|m| m.extra.clone())
+            .unwrap_or_default(),
+    ));
+    let taxonomies = site
+        .taxonomies
+        .iter()
+        .filter_map(|taxonomy| {
+            let terms = metadata
+                .as_ref()?
+                .terms(taxonomy)
+                .into_iter()
+                .map(|name| {
+                    let slug = term_slug(&name);
+                    Term {
+                        link: format!("{taxonomy}/{slug}/"),
+                        name,
+                        slug,
+                    }
+                })
+                .collect::<Vec<_>>();
+            (!terms.is_empty()).then(|| (taxonomy.clone(), terms))
+        })
+        .collect::<BTreeMap<_, _>>();
+    Ok(Some(Entity {
+        source: name.to_string(),
+        filename: slug,
+        link,
+        created_at_iso: date_str,
+        created_at,
+        title,
+        shortname,
+        head_title,
+        contents,
+        toc,
+        taxonomies,
+        draft,
+        scheduled,
+        meta,
+        excerpt: excerpt.or(metadata.and_then(|m| m.description)),
+        word_count,
+        reading_time: reading_time(word_count),
+    }))
+}
+
+async fn render_entity<'a>(
+    h: &Handlebars<'a>,
+    site: &SiteConfig,
+    collection: &Collection,
+    entities: &[Entity],
+    i: usize,
+    input_dir: &Path,
+    output_dir: &Path,
+) -> Result<()> {
+    let entity = &entities[i];
+    let (previous, next) = neighbours(entities, i);
+    let template_name = &collection.item_template;
     let out = h
         .render(
             template_name,
             &json!(EntityArgs {
+                site,
                 path: &[
-                    breadcrumbs,
-                    &[Breadcrumb {
-                        name: &shortname,
-                        link: &link,
-                    }]
-                ]
-                .concat(),
-                title: &title,
-                head_title: &head_title,
-                contents: &contents,
+                    Breadcrumb {
+                        name: &collection.title,
+                        link: &collection.path,
+                    },
+                    Breadcrumb {
+                        name: &entity.shortname,
+                        link: &entity.link,
+                    },
+                ],
+                title: &entity.title,
+                head_title: &entity.head_title,
+                contents: &entity.contents,
+                taxonomies: &entity.taxonomies,
+                draft: entity.draft,
+                scheduled: entity.scheduled,
+                meta: &entity.meta,
+                toc: &entity.toc,
+                word_count: entity.word_count,
+                reading_time: entity.reading_time,
+                previous: previous.map(EntitySummary::from),
+                next: next.map(EntitySummary::from),
             }),
         )
         .wrap_err(RenderError {
-            path: path.clone(),
+            path: input_dir.join(&entity.source),
             template_name: template_name.to_string(),
         })?;
 
-    write(output_dir.join(&slug), out)
+    let path = output_dir.join(&entity.filename);
+    write_html(site, &path, out)
         .await
         .wrap_err(IOError::Create { path })?;
+    Ok(())
+}
 
-    Ok(Entity {
-        filename: slug,
-        created_at_iso: date_str,
-        created_at,
-        title,
-    })
+#[allow(clippy::too_many_arguments)]
+async fn build_entities_page<'a>(
+    h: &Handlebars<'a>,
+    site: &SiteConfig,
+    name: &str,
+    breadcrumbs: &'a [Breadcrumb<'a>],
+    title: &str,
+    pagination: &Pagination,
+    entities: &[Entity],
+    output_dir: &Path,
+) -> Result<()> {
+    let page_output_dir = output_dir.join(page_path(pagination.current));
+    create_dir_all(&page_output_dir)
+        .await
+        .wrap_err(IOError::Create {
+            path: page_output_dir.clone(),
+        })?;
+    let entity_index_path = page_output_dir.join("index.html");
+    let out = h
+        .render(
+            name,
+            &json!(EntitiesArgs {
+                site,
+                path: breadcrumbs,
+                title,
+                entities,
+                pagination,
+            }),
+        )
+        .wrap_err(RenderError {
+            path: entity_index_path.clone(),
+            template_name: name.to_string(),
+        })?;
+    write_html(site, &entity_index_path, out)
+        .await
+        .wrap_err(IOError::Create {
+            path: entity_index_path,
+        })?;
+    Ok(())
 }
 
 pub async fn build_entities<'a>(
     h: &Handlebars<'a>,
-    name: String,
+    site: &SiteConfig,
+    options: &BuildOptions,
+    collection: &Collection,
     input_dir: &Path,
     output_dir: &Path,
-) -> Result<()> {
-    let title_case = name.to_title_case();
-    let breadcrumbs = &[Breadcrumb {
-        name: &title_case,
-        link: &name,
-    }];
+    warnings: &Warnings,
+) -> Result<Vec<Entity>> {
+    let entities_input_dir = input_dir.join(&collection.name);
+    let entities_output_dir = output_dir.join(&collection.path);
+    let mut entities = read_entities(site, options, collection, input_dir, warnings).await?;
+
+    // entities are sorted in the collection order for the index
+    collection.sort(&mut entities);
+    try_join_all((0..entities.len()).map(|i| {
+        render_entity(
+            h,
+            site,
+            collection,
+            &entities,
+            i,
+            &entities_input_dir,
+            &entities_output_dir,
+        )
+    }))
+    .await
+    .wrap_err(eyre!(
+        "Failed to build entity in collection \"{}\"",
+        collection.name
+    ))?;
+
+    build_collection_index(h, site, collection, &entities, output_dir).await?;
+    Ok(entities)
+}
 
-    let entities_input_dir = input_dir.join(&name);
-    let entities_output_dir = output_dir.join(&name);
+async fn read_entities(
+    site: &SiteConfig,
+    options: &BuildOptions,
+    collection: &Collection,
+    input_dir: &Path,
+    warnings: &Warnings,
+) -> Result<Vec<Entity>> {
+    let entities_input_dir = input_dir.join(&collection.name);
     let entries = get_entries_in_dir(&entities_input_dir)
         .await
         .wrap_err(IOError::Read {
-            path: name.clone().into(),
+            path: entities_input_dir.clone(),
         })?;
-    let mut entities = try_join_all(
+    let entities = try_join_all(
         entries
             .iter()
             .filter(|(filename, metadata, ..)| metadata.is_file() && filename.ends_with(".md"))
             .map(|(filename, ..)| {
-                build_entity(
-                    h,
+                read_entity(
+                    site,
+                    options,
                     filename,
-                    &name,
-                    breadcrumbs,
+                    collection,
                     &entities_input_dir,
-                    &entities_output_dir,
+                    warnings,
                 )
             }),
     )
     .await
-    .wrap_err(eyre!("Failed to build entity in collection \"{}\"", name))?;
+    .wrap_err(eyre!(
+        "Failed to build entity in collection \"{}\"",
+        collection.name
+    ))?
+    .into_iter()
+    .flatten()
+    .collect();
+    Ok(entities)
+}
+
+async fn build_collection_index<'a>(
+    h: &Handlebars<'a>,
+    site: &SiteConfig,
+    collection: &Collection,
+    entities: &[Entity],
+    output_dir: &Path,
+) -> Result<()> {
+    let breadcrumbs = &[Breadcrumb {
+        name: &collection.title,
+        link: &collection.path,
+    }];
+    let entities_output_dir = output_dir.join(&collection.path);
+
+    if let Some(base_url) = site
+        .base_url
+        .as_ref()
+        .filter(|_| site.feeds.is_enabled_for(&collection.name))
+    {
+        let rss_path = entities_output_dir.join("feed.xml");
+        write(
+            &rss_path,
+            rss_feed(
+                site,
+                base_url,
+                &collection.path,
+                &collection.title,
+                entities,
+            ),
+        )
+        .await
+        .wrap_err(IOError::Create { path: rss_path })?;
+        let atom_path = entities_output_dir.join("atom.xml");
+        write(
+            &atom_path,
+            atom_feed(
+                site,
+                base_url,
+                &collection.path,
+                &collection.title,
+                entities,
+            ),
+        )
+        .await
+        .wrap_err(IOError::Create { path: atom_path })?;
+    }
 
-    entities.sort_by_key(|e| Reverse(e.created_at_iso.clone()));
+    if !collection.render_index {
+        return Ok(());
+    }
+    let pages = paginate(entities, site.paginate, &collection.path);
+    try_join_all(pages.iter().map(|(pagination, page_entities)| {
+        build_entities_page(
+            h,
+            site,
+            &collection.index_template,
+            breadcrumbs,
+            &collection.title,
+            pagination,
+            page_entities,
+            &entities_output_dir,
+        )
+    }))
+    .await?;
+    Ok(())
+}
 
-    let entity_index_path = entities_output_dir.join("index.html");
+async fn build_term<'a>(
+    h: &Handlebars<'a>,
+    site: &SiteConfig,
+    taxonomy: &str,
+    breadcrumbs: &'a [Breadcrumb<'a>],
+    term: &Term,
+    entities: &[Entity],
+    output_dir: &Path,
+) -> Result<()> {
+    let term_output_dir = output_dir.join(taxonomy).join(&term.slug);
+    create_dir_all(&term_output_dir)
+        .await
+        .wrap_err(IOError::Create {
+            path: term_output_dir.clone(),
+        })?;
+    let term_path = term_output_dir.join("index.html");
+    let template_name = site.term_template(taxonomy);
     let out = h
         .render(
-            &name,
+            template_name,
             &json!(EntitiesArgs {
+                site,
+                path: &[
+                    breadcrumbs,
+                    &[Breadcrumb {
+                        name: &term.name,
+                        link: &term.link,
+                    }]
+                ]
+                .concat(),
+                title: &term.name,
+                entities,
+                pagination: &Pagination {
+                    current: 1,
+                    total: 1,
+                    previous: None,
+                    next: None,
+                },
+            }),
+        )
+        .wrap_err(RenderError {
+            path: term_path.clone(),
+            template_name: template_name.to_string(),
+        })?;
+    write_html(site, &term_path, out)
+        .await
+        .wrap_err(IOError::Create { path: term_path })?;
+    Ok(())
+}
+
+async fn build_taxonomy<'a>(
+    h: &Handlebars<'a>,
+    site: &SiteConfig,
+    taxonomy: &str,
+    entities: &[&Entity],
+    output_dir: &Path,
+) -> Result<()> {
+    let mut terms: BTreeMap<&str, (&Term, Vec<Entity>)> = BTreeMap::new();
+    for entity in entities {
+        for term in entity.taxonomies.get(taxonomy).into_iter().flatten() {
+            terms
+                .entry(&term.slug)
+                .or_insert_with(|| (term, Vec::new()))
+                .1
+                .push((*entity).clone());
+        }
+    }
+    if terms.is_empty() {
+        return Ok(());
+    }
+
+    let title_case = taxonomy.to_title_case();
+    let breadcrumbs = &[Breadcrumb {
+        name: &title_case,
+        link: taxonomy,
+    }];
+    try_join_all(terms.values_mut().map(|(term, term_entities)| {
+        term_entities.sort_by_key(|e| Reverse(e.created_at_iso.clone()));
+        build_term(
+            h,
+            site,
+            taxonomy,
+            breadcrumbs,
+            term,
+            term_entities,
+            output_dir,
+        )
+    }))
+    .await
+    .wrap_err(eyre!("Failed to build term in taxonomy \"{}\"", taxonomy))?;
+
+    let term_summaries = terms
+        .values()
+        .map(|(term, term_entities)| TermSummary {
+            term,
+            count: term_entities.len(),
+        })
+        .collect::<Vec<_>>();
+    let taxonomy_index_path = output_dir.join(taxonomy).join("index.html");
+    let out = h
+        .render(
+            taxonomy,
+            &json!(TermsArgs {
+                site,
                 path: breadcrumbs,
                 title: &title_case,
-                entities,
+                terms: &term_summaries,
             }),
         )
         .wrap_err(RenderError {
-            path: entity_index_path.clone(),
-            template_name: name,
+            path: taxonomy_index_path.clone(),
+            template_name: taxonomy.to_string(),
         })?;
-    write(&entity_index_path, out)
+    write_html(site, &taxonomy_index_path, out)
         .await
         .wrap_err(IOError::Create {
-            path: entity_index_path,
+            path: taxonomy_index_path,
         })?;
     Ok(())
 }
 
-pub async fn run_build(
-    console: &mut ConsoleHandle,
-    input_dir: &Path,
+async fn build_sitemap<'a>(
+    base_url: &str,
+    entities: impl Iterator<Item = &'a Entity>,
     output_dir: &Path,
-    should_confirm: bool,
 ) -> Result<()> {
-    let mut start = Utc::now();
+    let mut lastmods = HashMap::new();
+    for entity in entities {
+        lastmods.insert(entity.link.clone(), entity.created_at_iso.clone());
+        // collection index is as fresh as its newest entity
+        if let Some((collection_path, _)) = entity.link.rsplit_once('/') {
+            let index_link = format!("{collection_path}/index.html");
+            let lastmod = lastmods.entry(index_link).or_default();
+            if *lastmod < entity.created_at_iso {
+                *lastmod = entity.created_at_iso.clone();
+            }
+        }
+    }
+    let mut entries = get_files_in_dir_recursive(output_dir)
+        .into_iter()
+        .filter(|p| {
+            p.extension().is_some_and(|e| e == "html")
+                && !p
+                    .components()
+                    .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+        })
+        .map(|p| {
+            let path = p
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            SitemapEntry {
+                lastmod: lastmods.get(&path).cloned(),
+                path,
+            }
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
 
-    // check that input dir exists
-    metadata(&input_dir)
+    let sitemap_path = output_dir.join("sitemap.xml");
+    write(&sitemap_path, sitemap(base_url, &entries))
         .await
-        .wrap_err(eyre!("\"{}\" does not exist", input_dir.display()))?;
+        .wrap_err(IOError::Create { path: sitemap_path })?;
+    let robots_path = output_dir.join("robots.txt");
+    write(&robots_path, robots_txt(base_url))
+        .await
+        .wrap_err(IOError::Create { path: robots_path })?;
+    Ok(())
+}
 
-    // confirm output dir overwrite if it exists
-    if let Ok(metadata) = metadata(&output_dir).await {
-        if metadata.is_file() {
-            return Err(eyre!("{} is already a file", output_dir.display()));
-        }
+async fn build_indexes(state: &BuildState, output_dir: &Path) -> Result<()> {
+    build_taxonomies(state, output_dir).await?;
 
-        if should_confirm {
-            let ans = Confirm::new(
-                format!("{} already exists. Continue?", output_dir.display()).as_ref(),
-            )
-            .with_default(false)
-            .with_help_message("All contents will be overwritten except .git/")
-            .prompt()?;
-            if !ans {
-                return Ok(());
-            };
+    // build sitemap and robots.txt
+    if let Some(base_url) = &state.site.base_url {
+        let entities = state
+            .collections
+            .values()
+            .flat_map(|(_, entities)| entities);
+        build_sitemap(base_url, entities, output_dir).await?;
+    }
+    Ok(())
+}
 
-            start = Utc::now();
-        }
+async fn build_taxonomies(state: &BuildState, output_dir: &Path) -> Result<()> {
+    let entities = state
+        .collections
+        .values()
+        .flat_map(|(_, entities)| entities)
+        .collect::<Vec<_>>();
+    try_join_all(
+        state
+            .site
+            .taxonomies
+            .iter()
+            .map(|taxonomy| build_taxonomy(&state.h, &state.site, taxonomy, &entities, output_dir)),
+    )
+    .await?;
+    Ok(())
+}
 
-        let output_entries = get_entries_in_dir(output_dir)
-            .await
-            .wrap_err(IOError::Read {
-                path: output_dir.into(),
-            })?;
-        let reserved_output_filenames = [".git", "CNAME"];
-        try_join_all(
-            output_entries
-                .into_iter()
-                .filter_map(|(name, metadata, path)| {
-                    if reserved_output_filenames.contains(&name.as_str()) {
-                        None
-                    } else {
-                        Some(remove_path(metadata, path))
-                    }
-                }),
+/// Returns the directory of the theme set in `stoic.toml`, which is relative to the site.
+async fn theme_dir(input_dir: &Path, site: &SiteConfig) -> Result<Option<PathBuf>> {
+    let Some(theme) = &site.theme else {
+        return Ok(None);
+    };
+    let dir = canonicalize(input_dir.join(theme))
+        .await
+        .ok()
+        .filter(|dir| dir.is_dir())
+        .ok_or(eyre!("Theme \"{}\" does not exist", theme))?;
+    Ok(Some(dir))
+}
+
+/// Templates are resolved from the site, then the theme, then the embedded defaults.
+async fn read_templates(
+    input_dir: &Path,
+    site: &SiteConfig,
+    assets: &BTreeMap<String, String>,
+) -> Result<Handlebars<'static>> {
+    let mut h = Handlebars::new();
+    register_helpers(&mut h);
+    h.register_helper("asset", Box::new(Asset(assets.clone())));
+    for name in TemplateName::iter() {
+        h.register_template_string(&name.to_string(), name.template_str())?;
+    }
+
+    let theme_dir = theme_dir(input_dir, site).await?;
+    let templates_input_dirs = theme_dir
+        .iter()
+        .map(|dir| dir.join("templates"))
+        .chain([input_dir.join("templates")]);
+    for templates_input_dir in templates_input_dirs {
+        let template_entries = match get_entries_in_dir(&templates_input_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                return Err(e).wrap_err(IOError::Read {
+                    path: templates_input_dir,
+                })
+            }
+        };
+        let templates = try_join_all(
+            template_entries
+                .iter()
+                .map(|(n, ..)| read_template(n.to_string(), &templates_input_dir)),
         )
         .await
         .wrap_err(eyre!(
-            "Failed to remove contents of \"{}\"",
-            output_dir.display()
+            "Failed to read files in \"{}\"",
+            templates_input_dir.display()
         ))?;
-    } else {
-        create_dir(&output_dir).await.wrap_err(IOError::Create {
-            path: output_dir.into(),
-        })?;
+        for (name, template) in templates {
+            h.register_template_string(&name, template)?;
+        }
     }
+    Ok(h)
+}
 
-    console.log("Building...")?;
+/// Copies the assets to the output, fingerprinting their filenames when enabled, and returns the
+/// fingerprinted paths which are also written to `asset-manifest.json`.
+async fn build_assets(
+    site: &SiteConfig,
+    assets: BTreeMap<PathBuf, PathBuf>,
+    output_dir: &Path,
+) -> Result<BTreeMap<String, String>> {
+    let assets_output_dir = output_dir.join("assets");
+    let fingerprint = site.fingerprint_assets;
+    let mut manifest = BTreeMap::new();
 
-    // get pages and collections
-    let reserved_filenames = ["README.md", "readme.md"];
-    let reserved_dirnames = [".git", "assets", "templates"];
-    let input_entries = get_entries_in_dir(input_dir)
-        .await
-        .wrap_err(IOError::Read {
-            path: input_dir.into(),
-        })?;
-    let page_names = input_entries.iter().filter_map(|(name, metadata, _)| {
-        if metadata.is_file()
-            && name.ends_with(".md")
-            && !reserved_filenames.contains(&name.as_str())
-        {
-            Some(name)
-        } else {
-            None
-        }
-    });
-    let collection_names = input_entries.iter().filter_map(|(name, metadata, _)| {
-        if metadata.is_dir() && !reserved_dirnames.contains(&name.as_str()) {
-            Some(name)
-        } else {
-            None
+    // a custom highlight.css takes precedence over the generated one
+    if let Some(highlight) = site
+        .highlight
+        .as_ref()
+        .filter(|_| !assets.contains_key(Path::new("highlight.css")))
+    {
+        let mut css = theme_css(highlight)?.into_bytes();
+        if site.minify {
+            css = minify_asset(Path::new("highlight.css"), css)?;
         }
-    });
-
-    // create root level dirs assets and collections
-    let assets_output_dir = output_dir.join("assets");
-    try_join_all(
-        [create_dir(assets_output_dir.clone())].into_iter().chain(
-            collection_names
-                .clone()
-                .map(|n| create_dir(output_dir.join(n))),
-        ),
+        manifest.extend(
+            write_asset(
+                Path::new("highlight.css"),
+                css,
+                &assets_output_dir,
+                fingerprint,
+            )
+            .await?,
+        );
+    }
+
+    let built = try_join_all(assets.into_iter().map(|(file_path, input_path)| {
+        let assets_output_dir = &assets_output_dir;
+        async move {
+            if !fingerprint {
+                copy_asset(site, input_path, assets_output_dir.join(file_path)).await?;
+                return Ok(None);
+            }
+            let contents = read_asset(site, &input_path).await?;
+            write_asset(&file_path, contents, assets_output_dir, fingerprint).await
+        }
+    }))
+    .await?;
+    manifest.extend(built.into_iter().flatten());
+
+    if fingerprint {
+        let manifest_path = output_dir.join("asset-manifest.json");
+        write(&manifest_path, serde_json::to_string_pretty(&manifest)?)
+            .await
+            .wrap_err(IOError::Create {
+                path: manifest_path,
+            })?;
+    }
+    Ok(manifest)
+}
+
+/// Reads an asset, minifying CSS and JS when enabled.
+async fn read_asset(site: &SiteConfig, input_path: &Path) -> Result<Vec<u8>> {
+    let contents = read(input_path).await.wrap_err(IOError::Read {
+        path: input_path.into(),
+    })?;
+    if !site.minify {
+        return Ok(contents);
+    }
+    minify_asset(input_path, contents)
+        .wrap_err(eyre!("Failed to minify \"{}\"", input_path.display()))
+}
+
+/// Copies an asset, minifying CSS and JS when enabled.
+async fn copy_asset(site: &SiteConfig, input_path: PathBuf, output_path: PathBuf) -> Result<()> {
+    if !(site.minify && is_minifiable(&input_path)) {
+        return copy_file(input_path, output_path).await;
+    }
+    let contents = read_asset(site, &input_path).await?;
+    if let Some(parent) = output_path.parent() {
+        create_dir_all(parent).await.wrap_err(IOError::Create {
+            path: parent.into(),
+        })?;
+    }
+    write(&output_path, contents)
+        .await
+        .wrap_err(IOError::Create { path: output_path })
+}
+
+/// Writes an asset and returns its path and fingerprinted path if it was fingerprinted.
+/// Fingerprinted assets are also written under their own path, so that links to them from
+/// markdown and stylesheets, which don't go through the `asset` helper, keep working.
+async fn write_asset(
+    file_path: &Path,
+    contents: Vec<u8>,
+    assets_output_dir: &Path,
+    fingerprint: bool,
+) -> Result<Option<(String, String)>> {
+    let output_path = assets_output_dir.join(file_path);
+    if let Some(parent) = output_path.parent() {
+        create_dir_all(parent).await.wrap_err(IOError::Create {
+            path: parent.into(),
+        })?;
+    }
+    if !fingerprint {
+        write(&output_path, contents)
+            .await
+            .wrap_err(IOError::Create { path: output_path })?;
+        return Ok(None);
+    }
+    let fingerprinted_file_path = fingerprint_path(file_path, &contents);
+    let fingerprinted_path = assets_output_dir.join(&fingerprinted_file_path);
+    write(&fingerprinted_path, &contents)
+        .await
+        .wrap_err(IOError::Create {
+            path: fingerprinted_path,
+        })?;
+    write(&output_path, contents)
+        .await
+        .wrap_err(IOError::Create { path: output_path })?;
+    Ok(Some((
+        url_path(file_path),
+        url_path(&fingerprinted_file_path),
+    )))
+}
+
+async fn get_pages_and_collections(input_dir: &Path) -> Result<(Vec<String>, Vec<String>)> {
+    let input_entries = get_entries_in_dir(input_dir)
+        .await
+        .wrap_err(IOError::Read {
+            path: input_dir.into(),
+        })?;
+    let page_names = input_entries
+        .iter()
+        .filter_map(|(name, metadata, _)| {
+            if metadata.is_file()
+                && name.ends_with(".md")
+                && !RESERVED_FILENAMES.contains(&name.as_str())
+            {
+                Some(name.clone())
+            } else {
+                None
+            }
+        })
+        .collect();
+    let collection_names = input_entries
+        .iter()
+        .filter_map(|(name, metadata, _)| {
+            if metadata.is_dir() && !RESERVED_DIRNAMES.contains(&name.as_str()) {
+                Some(name.clone())
+            } else {
+                None
+            }
+        })
+        .collect();
+    Ok((page_names, collection_names))
+}
+
+/// Metadata errors which are reported once a build is done unless the build is strict.
+type Warnings = RefCell<Vec<Report>>;
+
+fn log_warnings(console: &mut ConsoleHandle, warnings: Warnings) -> Result<()> {
+    for warning in warnings.into_inner() {
+        console.log_warning(&warning.to_string())?;
+    }
+    Ok(())
+}
+
+/// The results of a full build which watch applies incremental rebuilds to.
+pub struct BuildState {
+    pub site: SiteConfig,
+    pub h: Handlebars<'static>,
+    pub pages: Vec<String>,
+    /// fingerprinted asset paths by their path in `assets/`
+    pub assets: BTreeMap<String, String>,
+    pub collections: BTreeMap<String, (Collection, Vec<Entity>)>,
+}
+
+pub async fn run_build(
+    console: &mut ConsoleHandle,
+    input_dir: &Path,
+    output_dir: &Path,
+    should_confirm: bool,
+    options: &BuildOptions,
+) -> Result<()> {
+    build(console, input_dir, output_dir, should_confirm, options).await?;
+    Ok(())
+}
+
+pub async fn run_check_links(
+    console: &mut ConsoleHandle,
+    input_dir: &Path,
+    output_dir: &Path,
+) -> Result<()> {
+    metadata(&output_dir)
+        .await
+        .wrap_err(eyre!("\"{}\" does not exist", output_dir.display()))?;
+    let site = read_site_config(input_dir).await?;
+    let (page_names, collection_names) = get_pages_and_collections(input_dir).await?;
+    let collections = try_join_all(
+        collection_names
+            .iter()
+            .map(|name| read_collection(input_dir, name)),
     )
-    .await
-    .wrap_err(eyre!("Failed to create output directories"))?;
+    .await?;
+
+    // entities are only read to find the source of each html file so every one is included
+    let options = BuildOptions {
+        drafts: true,
+        future: true,
+        ..Default::default()
+    };
+    let warnings = Warnings::default();
+    let entities = try_join_all(
+        collections
+            .iter()
+            .map(|c| read_entities(&site, &options, c, input_dir, &warnings)),
+    )
+    .await?;
+    let sources = link_sources(
+        input_dir,
+        &page_names,
+        collections.iter().zip(entities.iter().map(Vec::as_slice)),
+    );
 
-    // get asset file paths
-    let assets_input_dir = input_dir.join("assets");
-    let assets_file_paths = get_files_in_dir_recursive(&assets_input_dir);
+    let broken_links = check_links(output_dir, &sources, |path| {
+        output_file_exists(output_dir, path)
+    })
+    .await?;
+    for broken_link in &broken_links {
+        console.log_warning(&broken_link.to_string())?;
+    }
+    if !broken_links.is_empty() {
+        return Err(eyre!("Found {} broken links", broken_links.len()));
+    }
+    console.log("No broken links found")?;
+    Ok(())
+}
 
-    // read and register templates
-    let templates_input_dir = input_dir.join("templates");
-    let template_entries =
-        get_entries_in_dir(&templates_input_dir)
+async fn build(
+    console: &mut ConsoleHandle,
+    input_dir: &Path,
+    output_dir: &Path,
+    should_confirm: bool,
+    options: &BuildOptions,
+) -> Result<Option<BuildState>> {
+    let mut start = Utc::now();
+
+    // check that input dir exists
+    metadata(&input_dir)
+        .await
+        .wrap_err(eyre!("\"{}\" does not exist", input_dir.display()))?;
+
+    // read site config
+    let mut site = read_site_config(input_dir).await?;
+    site.minify |= options.minify;
+
+    // confirm output dir overwrite if it exists
+    if let Ok(metadata) = metadata(&output_dir).await {
+        if metadata.is_file() {
+            return Err(eyre!("{} is already a file", output_dir.display()));
+        }
+
+        if should_confirm {
+            let ans = Confirm::new(
+                format!("{} already exists. Continue?", output_dir.display()).as_ref(),
+            )
+            .with_default(false)
+            .with_help_message("All contents will be overwritten except preserved files")
+            .prompt()?;
+            if !ans {
+                return Ok(None);
+            };
+
+            start = Utc::now();
+        }
+    }
+
+    console.log("Building...")?;
+
+    let warnings = Warnings::default();
+    let result = build_output(input_dir, output_dir, site, options, &warnings).await;
+    log_warnings(console, warnings)?;
+    let state = result?;
+    console.log_elapsed((Utc::now() - start).num_milliseconds())?;
+
+    Ok(Some(state))
+}
+
+/// Builds the site into a staging dir next to the output dir and only then moves it into place,
+/// so that a failed build leaves the output dir as is.
+pub async fn build_output(
+    input_dir: &Path,
+    output_dir: &Path,
+    site: SiteConfig,
+    options: &BuildOptions,
+    warnings: &Warnings,
+) -> Result<BuildState> {
+    let preserve = preserve_globs(&site.preserve_patterns()).wrap_err(ConfigError {
+        path: input_dir.join("stoic.toml"),
+    })?;
+    let output_dir = &resolve_output_dir(output_dir).await;
+    let staging_dir = sibling_path(output_dir, "staging");
+    remove_if_exists(&staging_dir)
+        .await
+        .wrap_err(IOError::Create {
+            path: staging_dir.clone(),
+        })?;
+    create_dir(&staging_dir).await.wrap_err(IOError::Create {
+        path: staging_dir.clone(),
+    })?;
+    let staged = stage_site(
+        input_dir,
+        &staging_dir,
+        output_dir,
+        site,
+        options,
+        &preserve,
+        warnings,
+    );
+    let state = match staged.await {
+        Ok(state) => state,
+        Err(report) => {
+            // the report of the failed build is more useful than a failure to clean up
+            let _ = remove_if_exists(&staging_dir).await;
+            return Err(report);
+        }
+    };
+    // a mount point or a locked file can't be renamed, but its contents can still be synced
+    if !options.clean
+        || swap_output_dir(&staging_dir, output_dir, &preserve)
+            .await
+            .is_err()
+    {
+        sync_output_dir(&staging_dir, output_dir, &preserve).await?;
+    }
+    Ok(state)
+}
+
+/// Resolves a symlinked output dir i.e. one pointing at a checkout elsewhere, so that it is
+/// written through rather than replaced.
+async fn resolve_output_dir(output_dir: &Path) -> PathBuf {
+    canonicalize(output_dir)
+        .await
+        .unwrap_or(output_dir.to_path_buf())
+}
+
+/// Builds the site into the staging dir and checks its links if asked to.
+async fn stage_site(
+    input_dir: &Path,
+    staging_dir: &Path,
+    output_dir: &Path,
+    site: SiteConfig,
+    options: &BuildOptions,
+    preserve: &GlobSet,
+    warnings: &Warnings,
+) -> Result<BuildState> {
+    let state = build_site(input_dir, staging_dir, site, options, warnings).await?;
+    if !options.check_links {
+        return Ok(state);
+    }
+
+    let sources = link_sources(
+        input_dir,
+        &state.pages,
+        state.collections.values().map(|(c, e)| (c, e.as_slice())),
+    );
+    // preserved files are only moved into the staging dir when it is swapped in
+    let broken_links = check_links(staging_dir, &sources, |path| {
+        output_file_exists(staging_dir, path)
+            || (Path::new(path).ancestors().any(|p| preserve.is_match(p))
+                && output_file_exists(output_dir, path))
+    })
+    .await?;
+    let count = broken_links.len();
+    warnings
+        .borrow_mut()
+        .extend(broken_links.into_iter().map(Report::from));
+    if options.strict && count > 0 {
+        return Err(eyre!("Found {count} broken links"));
+    }
+    Ok(state)
+}
+
+/// Returns a hidden path next to `path` i.e. `.dist.staging` for `dist`.
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{name}.{suffix}"))
+}
+
+/// Replaces the output dir with the staging dir, keeping preserved entries of the output dir.
+/// On failure the output dir and its preserved entries are put back as they were.
+async fn swap_output_dir(staging_dir: &Path, output_dir: &Path, preserve: &GlobSet) -> Result<()> {
+    if metadata(output_dir).await.is_err() {
+        return rename(staging_dir, output_dir)
             .await
-            .wrap_err(IOError::Read {
-                path: templates_input_dir.clone(),
+            .wrap_err(IOError::Create {
+                path: output_dir.into(),
+            });
+    }
+
+    let mut moved = Vec::new();
+    let result = async {
+        for path in get_preserved_paths(output_dir, preserve) {
+            let staging_path = staging_dir.join(&path);
+            // the built file wins over a preserved one
+            if metadata(&staging_path).await.is_ok() {
+                continue;
+            }
+            if let Some(parent) = staging_path.parent() {
+                create_dir_all(parent).await.wrap_err(IOError::Create {
+                    path: parent.into(),
+                })?;
+            }
+            rename(output_dir.join(&path), &staging_path)
+                .await
+                .wrap_err(IOError::Create { path: staging_path })?;
+            moved.push(path);
+        }
+        let old_dir = sibling_path(output_dir, "old");
+        remove_if_exists(&old_dir).await.wrap_err(IOError::Create {
+            path: old_dir.clone(),
+        })?;
+        rename(output_dir, &old_dir)
+            .await
+            .wrap_err(IOError::Create {
+                path: old_dir.clone(),
             })?;
-    let templates = try_join_all(
-        template_entries
+        if let Err(e) = rename(staging_dir, output_dir).await {
+            let _ = rename(&old_dir, output_dir).await;
+            return Err(e).wrap_err(IOError::Create {
+                path: output_dir.into(),
+            });
+        }
+        Ok(old_dir)
+    }
+    .await;
+
+    match result {
+        Ok(old_dir) => remove_dir_all(&old_dir)
+            .await
+            .wrap_err(eyre!("Failed to remove \"{}\"", old_dir.display())),
+        Err(report) => {
+            // preserved entries must not be left in the staging dir, which is removed later
+            for path in moved {
+                let _ = rename(staging_dir.join(&path), output_dir.join(&path)).await;
+            }
+            Err(report)
+        }
+    }
+}
+
+/// Moves changed files from the staging dir into the output dir and removes stale ones,
+/// leaving unchanged and preserved files untouched.
+async fn sync_output_dir(staging_dir: &Path, output_dir: &Path, preserve: &GlobSet) -> Result<()> {
+    create_dir_all(output_dir).await.wrap_err(IOError::Create {
+        path: output_dir.into(),
+    })?;
+    remove_stale_paths(output_dir, staging_dir, preserve).await?;
+    move_changed_files(staging_dir, output_dir).await?;
+    remove_dir_all(staging_dir)
+        .await
+        .wrap_err(eyre!("Failed to remove \"{}\"", staging_dir.display()))
+}
+
+/// Removes the files in `output_dir` that are not in `built_dir` and the dirs left empty.
+async fn remove_stale_paths(output_dir: &Path, built_dir: &Path, preserve: &GlobSet) -> Result<()> {
+    for (path, is_dir) in get_stale_paths(output_dir, built_dir, preserve) {
+        if !is_dir {
+            remove_file(&path)
+                .await
+                .wrap_err(eyre!("Failed to remove \"{}\"", path.display()))?;
+        } else if get_entries_in_dir(&path)
+            .await
+            .is_ok_and(|entries| entries.is_empty())
+        {
+            // dirs holding preserved files are kept
+            remove_dir(&path)
+                .await
+                .wrap_err(eyre!("Failed to remove \"{}\"", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Moves the files in `staging_dir` whose contents differ from those in `output_dir`.
+async fn move_changed_files(staging_dir: &Path, output_dir: &Path) -> Result<()> {
+    for path in get_files_in_dir_recursive(staging_dir) {
+        let staging_path = staging_dir.join(&path);
+        let output_path = output_dir.join(&path);
+        if let (Ok(built), Ok(existing)) = (read(&staging_path).await, read(&output_path).await) {
+            if built == existing {
+                continue;
+            }
+        }
+        if let Some(parent) = output_path.parent() {
+            create_dir_all(parent).await.wrap_err(IOError::Create {
+                path: parent.into(),
+            })?;
+        }
+        rename(&staging_path, &output_path)
+            .await
+            .wrap_err(IOError::Create { path: output_path })?;
+    }
+    Ok(())
+}
+
+/// Maps the paths of generated html files to the markdown files they were generated from.
+fn link_sources<'a>(
+    input_dir: &Path,
+    pages: &[String],
+    collections: impl Iterator<Item = (&'a Collection, &'a [Entity])>,
+) -> HashMap<String, PathBuf> {
+    let mut sources = pages
+        .iter()
+        .map(|name| (name.replace(".md", ".html"), input_dir.join(name)))
+        .collect::<HashMap<_, _>>();
+    for (collection, entities) in collections {
+        for entity in entities {
+            sources.insert(
+                entity.link.clone(),
+                input_dir.join(&collection.name).join(&entity.source),
+            );
+        }
+    }
+    sources
+}
+
+/// A link to a directory is served as its `index.html`.
+fn output_file_exists(output_dir: &Path, path: &str) -> bool {
+    let path = output_dir.join(path);
+    path.is_file() || path.join("index.html").is_file()
+}
+
+/// Returns the broken links of every html file in `output_dir`, reported against the markdown
+/// file in `sources` that the html file was generated from where there is one.
+async fn check_links(
+    output_dir: &Path,
+    sources: &HashMap<String, PathBuf>,
+    exists: impl Fn(&str) -> bool,
+) -> Result<Vec<LinkError>> {
+    let mut errors = Vec::new();
+    for file_path in get_files_in_dir_recursive(output_dir) {
+        if file_path.extension().is_none_or(|e| e != "html") {
+            continue;
+        }
+        let page = url_path(&file_path);
+        let path = output_dir.join(&file_path);
+        let html = read_to_string(&path)
+            .await
+            .wrap_err(IOError::Read { path: path.clone() })?;
+        let path = sources.get(&page).cloned().unwrap_or(path);
+        errors.extend(
+            broken_links(&page, &html, &exists)
+                .into_iter()
+                .map(|link| LinkError {
+                    path: path.clone(),
+                    link,
+                }),
+        );
+    }
+    Ok(errors)
+}
+
+async fn build_site(
+    input_dir: &Path,
+    output_dir: &Path,
+    site: SiteConfig,
+    options: &BuildOptions,
+    warnings: &Warnings,
+) -> Result<BuildState> {
+    // get pages and collections
+    let (page_names, collection_names) = get_pages_and_collections(input_dir).await?;
+    let collections = try_join_all(
+        collection_names
             .iter()
-            .map(|(n, ..)| read_template(n.to_string(), &templates_input_dir)),
+            .map(|name| read_collection(input_dir, name)),
+    )
+    .await?;
+    for collection in &collections {
+        check_collection_path(&site, collection, &collections)
+            .map_err(|message| eyre!(message))
+            .wrap_err(ConfigError {
+                path: input_dir
+                    .join(&collection.name)
+                    .join(COLLECTION_CONFIG_FILENAME),
+            })?;
+    }
+
+    // create root level dirs assets and collections
+    let assets_output_dir = output_dir.join("assets");
+    try_join_all(
+        [create_dir_all(assets_output_dir.clone())]
+            .into_iter()
+            .chain(
+                collections
+                    .iter()
+                    .map(|c| create_dir_all(output_dir.join(&c.path))),
+            ),
     )
     .await
-    .wrap_err("Failed to read files in \"templates/\"")?;
-    let mut h = Handlebars::new();
-    for (name, template) in templates {
-        h.register_template_string(&name, template)?;
+    .wrap_err(eyre!("Failed to create output directories"))?;
+
+    // get asset file paths, where site assets take precedence over theme assets
+    let theme_dir = theme_dir(input_dir, &site).await?;
+    let mut assets = BTreeMap::new();
+    for assets_input_dir in theme_dir
+        .iter()
+        .map(|dir| dir.join("assets"))
+        .chain([input_dir.join("assets")])
+    {
+        for file_path in get_files_in_dir_recursive(&assets_input_dir) {
+            assets.insert(file_path.clone(), assets_input_dir.join(file_path));
+        }
     }
 
+    // build assets before the templates that link to them
+    let assets = build_assets(&site, assets, output_dir).await?;
+
+    // read and register templates
+    let h = read_templates(input_dir, &site, &assets).await?;
+
     // build
     let build_actions = FuturesUnordered::new();
-    // build assets
-    for file_path in assets_file_paths {
+    // build collections
+    let collection_actions = try_join_all(collections.iter().map(|collection| {
+        build_entities(
+            &h, &site, options, collection, input_dir, output_dir, warnings,
+        )
+    }));
+    // build pages
+    for name in &page_names {
         build_actions.push(
-            copy_file(
-                assets_input_dir.join(&file_path),
-                assets_output_dir.join(file_path),
+            build_page(
+                &h,
+                &site,
+                options,
+                name.clone(),
+                input_dir,
+                output_dir,
+                warnings,
             )
             .boxed_local(),
-        );
+        )
     }
-    // build collections
-    for name in collection_names {
-        build_actions
-            .push(build_entities(&h, name.to_string(), input_dir, output_dir).boxed_local());
+    let (collection_entities, _) =
+        try_join(collection_actions, try_join_all(build_actions)).await?;
+
+    let state = BuildState {
+        site,
+        h,
+        pages: page_names,
+        assets,
+        collections: collections
+            .into_iter()
+            .zip(collection_entities)
+            .map(|(collection, entities)| (collection.name.clone(), (collection, entities)))
+            .collect(),
+    };
+    build_indexes(&state, output_dir).await?;
+    Ok(state)
+}
+
+async fn requires_full_build(
+    state: &BuildState,
+    changes: &BTreeSet<Change>,
+    input_dir: &Path,
+) -> bool {
+    for change in changes {
+        let is_full = match change {
+            Change::Full => true,
+            Change::Root(name) => {
+                state.collections.contains_key(name)
+                    || metadata(input_dir.join(name))
+                        .await
+                        .is_ok_and(|m| m.is_dir())
+            }
+            Change::Entity(collection_name, _) => !state.collections.contains_key(collection_name),
+            // a changed fingerprint changes every page linking to the asset and the generated
+            // highlight.css replaces a custom one that is removed
+            Change::Asset(path) => {
+                state.site.fingerprint_assets
+                    || (state.site.highlight.is_some() && path == Path::new("highlight.css"))
+            }
+            // partials such as base and nav are used by everything
+            Change::Template(name) => {
+                name != "page"
+                    && !state
+                        .collections
+                        .values()
+                        .any(|(c, _)| &c.index_template == name || &c.item_template == name)
+                    && !state
+                        .site
+                        .taxonomies
+                        .iter()
+                        .any(|t| t == name || state.site.term_template(t) == name)
+                    && metadata(input_dir.join(format!("{name}.md")))
+                        .await
+                        .is_err()
+            }
+            _ => false,
+        };
+        if is_full {
+            return true;
+        }
     }
-    // build pages
-    for name in page_names {
-        build_actions.push(build_page(&h, name.to_string(), input_dir, output_dir).boxed_local())
+    false
+}
+
+/// Rebuilds an entity and its neighbours, returning the path of its old output if it is gone.
+async fn rebuild_entity(
+    state: &mut BuildState,
+    options: &BuildOptions,
+    collection_name: &str,
+    name: &str,
+    input_dir: &Path,
+    output_dir: &Path,
+    warnings: &Warnings,
+) -> Result<Option<PathBuf>> {
+    let Some((collection, entities)) = state.collections.get_mut(collection_name) else {
+        return Ok(None);
+    };
+    let entities_input_dir = input_dir.join(&collection.name);
+    let entities_output_dir = output_dir.join(&collection.path);
+    let entity = if metadata(entities_input_dir.join(name)).await.is_ok() {
+        read_entity(
+            &state.site,
+            options,
+            name,
+            collection,
+            &entities_input_dir,
+            warnings,
+        )
+        .await?
+    } else {
+        None
+    };
+
+    // the entity and its neighbours before and after the change have to be rendered
+    let mut affected = BTreeSet::from([name.to_string()]);
+    affected.extend(neighbour_sources(entities, name));
+    let mut removed = None;
+    if let Some(i) = entities.iter().position(|e| e.source == name) {
+        let old_entity = entities.remove(i);
+        if entity
+            .as_ref()
+            .is_none_or(|e| e.filename != old_entity.filename)
+        {
+            removed = Some(Path::new(&collection.path).join(&old_entity.filename));
+        }
     }
-    try_join_all(build_actions).await?;
+    entities.extend(entity);
+    collection.sort(entities);
+    affected.extend(neighbour_sources(entities, name));
 
+    for (i, entity) in entities.iter().enumerate() {
+        if affected.contains(&entity.source) {
+            render_entity(
+                &state.h,
+                &state.site,
+                collection,
+                entities,
+                i,
+                &entities_input_dir,
+                &entities_output_dir,
+            )
+            .await?;
+        }
+    }
+    Ok(removed)
+}
+
+/// Paths relative to the output dir that an incremental rebuild replaces or removes.
+#[derive(Default)]
+struct StagedChanges {
+    /// dirs whose files missing from the staging dir are removed
+    replaced_dirs: Vec<PathBuf>,
+    removed: Vec<PathBuf>,
+    should_build_sitemap: bool,
+}
+
+/// Rebuilds only the outputs affected by `changes`. They are rendered into a staging dir and only
+/// moved into the output dir once all of them succeed, so that a failed rebuild leaves it as is.
+async fn rebuild(
+    console: &mut ConsoleHandle,
+    state: &mut BuildState,
+    changes: &BTreeSet<Change>,
+    input_dir: &Path,
+    output_dir: &Path,
+    options: &BuildOptions,
+) -> Result<()> {
+    let start = Utc::now();
+    let warnings = Warnings::default();
+
+    let output_dir = &resolve_output_dir(output_dir).await;
+    let staging_dir = sibling_path(output_dir, "staging");
+    remove_if_exists(&staging_dir)
+        .await
+        .wrap_err(IOError::Create {
+            path: staging_dir.clone(),
+        })?;
+    let result = stage_rebuild(state, changes, input_dir, &staging_dir, options, &warnings).await;
+    let staged = match result {
+        Ok(staged) => staged,
+        Err(report) => {
+            let _ = remove_if_exists(&staging_dir).await;
+            log_warnings(console, warnings)?;
+            return Err(report);
+        }
+    };
+
+    let empty = GlobSet::empty();
+    for dir in &staged.replaced_dirs {
+        remove_stale_paths(&output_dir.join(dir), &staging_dir.join(dir), &empty).await?;
+    }
+    for path in &staged.removed {
+        let path = output_dir.join(path);
+        remove_if_exists(&path)
+            .await
+            .wrap_err(IOError::Create { path })?;
+    }
+    move_changed_files(&staging_dir, output_dir).await?;
+    remove_dir_all(&staging_dir)
+        .await
+        .wrap_err(eyre!("Failed to remove \"{}\"", staging_dir.display()))?;
+
+    // the sitemap lists the files in the output dir so it is built once they are in place
+    if let Some(base_url) = state
+        .site
+        .base_url
+        .as_ref()
+        .filter(|_| staged.should_build_sitemap)
+    {
+        let entities = state
+            .collections
+            .values()
+            .flat_map(|(_, entities)| entities);
+        build_sitemap(base_url, entities, output_dir).await?;
+    }
+
+    log_warnings(console, warnings)?;
     console.log_elapsed((Utc::now() - start).num_milliseconds())?;
 
     Ok(())
 }
 
+/// Renders the outputs affected by `changes` into `staging_dir`.
+async fn stage_rebuild(
+    state: &mut BuildState,
+    changes: &BTreeSet<Change>,
+    input_dir: &Path,
+    staging_dir: &Path,
+    options: &BuildOptions,
+    warnings: &Warnings,
+) -> Result<StagedChanges> {
+    let mut staged = StagedChanges::default();
+    let mut page_names = BTreeSet::new();
+    let mut collection_names = BTreeSet::new();
+    let mut collection_index_names = BTreeSet::new();
+    let mut should_build_indexes = false;
+
+    for dir in state
+        .collections
+        .values()
+        .map(|(collection, _)| staging_dir.join(&collection.path))
+        .chain([staging_dir.join("assets")])
+    {
+        create_dir_all(&dir)
+            .await
+            .wrap_err(IOError::Create { path: dir })?;
+    }
+
+    if changes.iter().any(|c| matches!(c, Change::Template(_))) {
+        state.h = read_templates(input_dir, &state.site, &state.assets).await?;
+        should_build_indexes = true;
+    }
+
+    for change in changes {
+        match change {
+            Change::Asset(path) => {
+                // a site asset overrides the theme asset with the same path
+                let mut asset_input_paths = vec![input_dir.join("assets").join(path)];
+                if let Some(theme_dir) = theme_dir(input_dir, &state.site).await? {
+                    asset_input_paths.push(theme_dir.join("assets").join(path));
+                }
+                let mut asset_input_path = None;
+                for path in asset_input_paths {
+                    if metadata(&path).await.is_ok_and(|m| m.is_file()) {
+                        asset_input_path = Some(path);
+                        break;
+                    }
+                }
+                match asset_input_path {
+                    Some(asset_input_path) => {
+                        let asset_output_path = staging_dir.join("assets").join(path);
+                        copy_asset(&state.site, asset_input_path, asset_output_path).await?;
+                    }
+                    None => staged.removed.push(Path::new("assets").join(path)),
+                }
+            }
+            Change::Page(name) => {
+                page_names.insert(name.clone());
+            }
+            Change::Entity(collection_name, name) => {
+                let removed = rebuild_entity(
+                    state,
+                    options,
+                    collection_name,
+                    name,
+                    input_dir,
+                    staging_dir,
+                    warnings,
+                )
+                .await?;
+                staged.removed.extend(removed);
+                collection_index_names.insert(collection_name.clone());
+                should_build_indexes = true;
+            }
+            Change::Template(name) => {
+                if name == "page" {
+                    let (all_page_names, _) = get_pages_and_collections(input_dir).await?;
+                    page_names.extend(
+                        all_page_names
+                            .into_iter()
+                            .filter(|n| !state.h.has_template(n.strip_suffix(".md").unwrap_or(n))),
+                    );
+                } else if metadata(input_dir.join(format!("{name}.md"))).await.is_ok() {
+                    page_names.insert(format!("{name}.md"));
+                }
+                for (collection_name, (collection, _)) in &state.collections {
+                    if &collection.index_template == name {
+                        collection_index_names.insert(collection_name.clone());
+                    } else if &collection.item_template == name {
+                        collection_names.insert(collection_name.clone());
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // rebuild collections whose item template changed
+    for collection_name in &collection_names {
+        let collection = state.collections[collection_name].0.clone();
+        staged.replaced_dirs.push(PathBuf::from(&collection.path));
+        let entities = build_entities(
+            &state.h,
+            &state.site,
+            options,
+            &collection,
+            input_dir,
+            staging_dir,
+            warnings,
+        )
+        .await?;
+        state
+            .collections
+            .insert(collection_name.clone(), (collection, entities));
+    }
+
+    // rebuild collection indexes whose entities or template changed
+    for collection_name in collection_index_names.difference(&collection_names) {
+        let (collection, entities) = &state.collections[collection_name];
+        staged
+            .replaced_dirs
+            .push(Path::new(&collection.path).join("page"));
+        build_collection_index(&state.h, &state.site, collection, entities, staging_dir).await?;
+    }
+
+    // rebuild pages
+    for name in page_names {
+        if metadata(input_dir.join(&name)).await.is_ok() {
+            build_page(
+                &state.h,
+                &state.site,
+                options,
+                name,
+                input_dir,
+                staging_dir,
+                warnings,
+            )
+            .await?;
+        } else {
+            staged
+                .removed
+                .push(PathBuf::from(name.replace(".md", ".html")));
+        }
+    }
+
+    // rebuild taxonomies
+    if should_build_indexes || !collection_names.is_empty() {
+        staged
+            .replaced_dirs
+            .extend(state.site.taxonomies.iter().map(PathBuf::from));
+        build_taxonomies(state, staging_dir).await?;
+        staged.should_build_sitemap = true;
+    }
+
+    Ok(staged)
+}
+
+/// Binds to `port` or, if it is taken, to one of the ports after it.
+async fn bind(host: &str, port: u16) -> Result<TcpListener> {
+    let last_port = port.saturating_add(PORT_ATTEMPTS - 1);
+    for port in port..=last_port {
+        match TcpListener::bind((host, port)).await {
+            Ok(listener) => return Ok(listener),
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse && port < last_port => continue,
+            Err(e) => return Err(e).wrap_err(eyre!("Failed to bind to {host}:{port}")),
+        }
+    }
+    unreachable!("the last port returns")
+}
+
 pub async fn run_watch(
     console: &mut ConsoleHandle,
     input_dir: &Path,
     output_dir: &Path,
+    options: &BuildOptions,
+    server_options: &ServerOptions,
 ) -> Result<()> {
-    run_build(console, input_dir, output_dir, false).await?;
+    // drafts and scheduled entities are always previewed while watching
+    let mut options = options.clone();
+    options.drafts = true;
+    options.future = true;
+    // event paths are absolute so the input dir must be too
+    let input_dir = canonicalize(input_dir)
+        .await
+        .wrap_err(eyre!("\"{}\" does not exist", input_dir.display()))?;
+    let mut state = build(console, &input_dir, output_dir, false, &options).await?;
+    let theme_dir = match &state {
+        Some(s) => theme_dir(&input_dir, &s.site).await?,
+        None => None,
+    };
 
     let livereload = LiveReloadLayer::new();
     let reloader = livereload.reloader();
     let output_dir_copy = output_dir.to_path_buf();
-    let address = "0.0.0.0:3030";
+    let listener = bind(&server_options.host, server_options.port).await?;
+    let address = listener.local_addr()?;
 
-    tokio::spawn(async move {
+    let server = tokio::spawn(async move {
         let app = axum::Router::new()
             .fallback_service(ServeDir::new(output_dir_copy))
             .layer(livereload);
-        let listener = TcpListener::bind(address).await.unwrap();
-        axum::serve(listener, app).await.unwrap();
-        panic!("Unexpected server exit");
+        axum::serve(listener, app).await
     });
-    console.set_address(address)?;
+    console.set_address(&address.to_string())?;
+
+    if server_options.open {
+        let url = if address.ip().is_unspecified() {
+            format!("http://localhost:{}", address.port())
+        } else {
+            format!("http://{address}")
+        };
+        if let Err(e) = open::that_detached(&url) {
+            console.log_warning(&format!("Failed to open {url}: {e}"))?;
+        }
+    }
 
     let (tx, rx) = mpsc::channel();
     let mut debouncer = new_debouncer(Duration::from_millis(250), None, tx)?;
-    debouncer.watch(input_dir, RecursiveMode::Recursive)?;
+    debouncer.watch(&input_dir, RecursiveMode::Recursive)?;
+    // a theme outside of the site is watched too and any change to it rebuilds the site
+    let theme_dir = theme_dir.filter(|dir| !dir.starts_with(&input_dir));
+    if let Some(dir) = &theme_dir {
+        debouncer.watch(dir, RecursiveMode::Recursive)?;
+    }
 
-    while let Ok(res) = rx.recv() {
+    loop {
+        // the server is checked on in between changes so that its errors are reported
+        let res = match rx.recv_timeout(Duration::from_millis(500)) {
+            Ok(res) => res,
+            Err(RecvTimeoutError::Timeout) if server.is_finished() => {
+                return match server.await? {
+                    Ok(()) => Err(eyre!("Server exited unexpectedly")),
+                    Err(e) => Err(e).wrap_err("Server failed"),
+                };
+            }
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
         match res {
             Ok(events) => {
-                if events.iter().any(|event| {
-                    matches!(
-                        event.kind,
-                        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
-                    )
-                }) {
-                    if let Err(report) = run_build(console, input_dir, output_dir, false).await {
-                        console.log_report(report)?;
-                    } else {
-                        reloader.reload();
+                let changes = events
+                    .iter()
+                    .filter(|event| {
+                        matches!(
+                            event.kind,
+                            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                        )
+                    })
+                    .flat_map(|event| event.paths.iter())
+                    .filter_map(|path| match path.strip_prefix(&input_dir) {
+                        Ok(path) => Some(classify_change(path)),
+                        Err(_) => theme_dir
+                            .as_ref()
+                            .and_then(|dir| path.strip_prefix(dir).ok())
+                            .map(classify_change)
+                            .map(|change| match change {
+                                Change::Ignored => Change::Ignored,
+                                _ => Change::Full,
+                            }),
+                    })
+                    .filter(|change| *change != Change::Ignored)
+                    .collect::<BTreeSet<_>>();
+                if changes.is_empty() {
+                    continue;
+                }
+
+                let is_incremental = match &state {
+                    Some(s) => !requires_full_build(s, &changes, &input_dir).await,
+                    None => false,
+                };
+                // a failed rebuild leaves no state behind so the next one is a full build
+                let result = match state.take() {
+                    Some(mut s) if is_incremental => {
+                        rebuild(console, &mut s, &changes, &input_dir, output_dir, &options)
+                            .await
+                            .map(|_| state = Some(s))
                     }
+                    _ => build(console, &input_dir, output_dir, false, &options)
+                        .await
+                        .map(|s| state = s),
+                };
+                if let Err(report) = result {
+                    console.log_report(report)?;
+                } else {
+                    reloader.reload();
                 }
             }
             Err(e) => panic!("{:?}", e),
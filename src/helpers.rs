@@ -0,0 +1,118 @@
+use crate::utils::{self, md_to_html};
+use chrono::{DateTime, NaiveDate, NaiveTime};
+use handlebars::{
+    handlebars_helper, Context, Handlebars, Helper, HelperDef, RenderContext, RenderError,
+    RenderErrorReason, ScopedJson,
+};
+use serde_json::Value as Json;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Registers the built-in helpers. `eq`, `ne`, `lt`, `gt`, `and`, `or` and `not` come with handlebars.
+pub fn register_helpers(h: &mut Handlebars) {
+    h.register_helper("date_format", Box::new(DateFormat));
+    h.register_helper("absolute_url", Box::new(AbsoluteUrl));
+    h.register_helper("slugify", Box::new(slugify));
+    h.register_helper("truncate", Box::new(truncate));
+    h.register_helper("markdownify", Box::new(markdownify));
+    h.register_helper("json", Box::new(json));
+    h.register_helper("limit", Box::new(limit));
+}
+
+handlebars_helper!(slugify: |s: str| utils::slugify(s));
+
+handlebars_helper!(truncate: |s: str, length: u64, { ellipsis: str = "…" }| {
+    let length = length as usize;
+    if s.chars().count() <= length {
+        s.to_string()
+    } else {
+        let truncated = s.chars().take(length).collect::<String>();
+        format!("{}{ellipsis}", truncated.trim_end())
+    }
+});
+
+handlebars_helper!(markdownify: |s: str| md_to_html(s, None, false).contents);
+
+handlebars_helper!(json: |value: Json| serde_json::to_string(value).unwrap_or_default());
+
+handlebars_helper!(limit: |items: array, n: u64| {
+    items.iter().take(n as usize).cloned().collect::<Vec<_>>()
+});
+
+fn str_param<'a>(h: &'a Helper, name: &'static str, index: usize) -> Result<&'a str, RenderError> {
+    h.param(index)
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex(name, index))?
+        .value()
+        .as_str()
+        .ok_or(RenderErrorReason::InvalidParamType("string").into())
+}
+
+/// Formats a `YYYY-MM-DD` date or an RFC 3339 datetime with a strftime format string
+/// i.e. `{{date_format created_at_iso "%B %e, %Y"}}`.
+struct DateFormat;
+
+impl HelperDef for DateFormat {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let date = str_param(h, "date_format", 0)?;
+        let format = str_param(h, "date_format", 1)?;
+        let datetime = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map(|d| d.and_time(NaiveTime::MIN).and_utc().fixed_offset())
+            .or_else(|_| DateTime::parse_from_rfc3339(date))
+            .map_err(|_| RenderErrorReason::Other(format!("\"{date}\" is not a date")))?;
+        // an invalid format is only detected when written
+        let mut out = String::new();
+        write!(out, "{}", datetime.format(format)).map_err(|_| {
+            RenderErrorReason::Other(format!("\"{format}\" is not a valid date format"))
+        })?;
+        Ok(ScopedJson::Derived(Json::String(out)))
+    }
+}
+
+/// Prefixes a path with the `base_url` of the site, or with `/` when there is none.
+struct AbsoluteUrl;
+
+impl HelperDef for AbsoluteUrl {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let path = str_param(h, "absolute_url", 0)?;
+        let base_url = ctx
+            .data()
+            .pointer("/site/base_url")
+            .and_then(Json::as_str)
+            .unwrap_or_default();
+        Ok(ScopedJson::Derived(Json::String(format!(
+            "{}/{}",
+            base_url.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        ))))
+    }
+}
+
+/// Resolves a path in `assets/` to the path it is written to, which differs when assets are
+/// fingerprinted i.e. `{{asset "style.css"}}`. Holds the fingerprinted paths by asset path.
+pub struct Asset(pub BTreeMap<String, String>);
+
+impl HelperDef for Asset {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let path = str_param(h, "asset", 0)?.trim_start_matches('/');
+        let path = self.0.get(path).map_or(path, String::as_str);
+        Ok(ScopedJson::Derived(Json::String(format!("assets/{path}"))))
+    }
+}
@@ -0,0 +1,57 @@
+use crate::types::HighlightConfig;
+use crate::utils::escape_xml;
+use color_eyre::eyre::{eyre, Result};
+use std::sync::LazyLock;
+use syntect::highlighting::ThemeSet;
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+// loading the syntax definitions is slow so they are loaded once and only if needed
+static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+
+pub fn is_supported(lang: &str) -> bool {
+    SYNTAX_SET.find_syntax_by_token(lang).is_some()
+}
+
+/// Highlights `code` as `lang` which should be supported.
+pub fn highlight_code(code: &str, lang: &str, config: &HighlightConfig) -> String {
+    let syntax = SYNTAX_SET
+        .find_syntax_by_token(lang)
+        .unwrap_or(SYNTAX_SET.find_syntax_plain_text());
+    let mut generator =
+        ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAX_SET, CLASS_STYLE);
+    let mut html = LinesWithEndings::from(code)
+        .try_for_each(|line| generator.parse_html_for_line_which_includes_newline(line))
+        .map(|_| generator.finalize())
+        .unwrap_or(escape_xml(code));
+    if config.line_numbers {
+        html = html
+            .lines()
+            .enumerate()
+            .map(|(i, l)| format!(r#"<span class="line-number">{}</span>{l}"#, i + 1))
+            .collect::<Vec<_>>()
+            .join("\n");
+        html.push('\n');
+    }
+    format!(
+        "<pre class=\"highlight hl-code\"><code class=\"language-{}\">{html}</code></pre>\n",
+        escape_xml(lang)
+    )
+}
+
+pub fn theme_css(config: &HighlightConfig) -> Result<String> {
+    let theme_set = ThemeSet::load_defaults();
+    let theme = theme_set.themes.get(&config.theme).ok_or(eyre!(
+        "Unknown highlight theme \"{}\", expected one of {:?}",
+        config.theme,
+        theme_set.themes.keys().collect::<Vec<_>>()
+    ))?;
+    let mut css = css_for_theme_with_class_style(theme, CLASS_STYLE)?;
+    css.push_str(
+        ".highlight .line-number {\n  display: inline-block;\n  min-width: 2em;\n  margin-right: 1em;\n  text-align: right;\n  opacity: 0.5;\n  user-select: none;\n}\n",
+    );
+    Ok(css)
+}
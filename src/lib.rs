@@ -1,7 +1,14 @@
 pub mod assets;
+pub mod changes;
 pub mod console;
 pub mod errors;
+pub mod feeds;
 pub mod handlers;
+pub mod helpers;
+pub mod highlight;
+pub mod links;
+pub mod minify;
+pub mod sitemap;
 pub mod templates;
 pub mod types;
 pub mod utils;
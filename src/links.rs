@@ -0,0 +1,240 @@
+use crate::utils::escape_xml;
+use std::ops::Range;
+
+/// Calls `f` with the tag name, the `href` or `src` attribute name and the byte range of its raw
+/// value for each link attribute in `html`, skipping comments and the contents of scripts and styles.
+fn scan_links(html: &str, mut f: impl FnMut(&str, Range<usize>)) {
+    let bytes = html.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"<!--") {
+            i = html[i..].find("-->").map_or(bytes.len(), |end| i + end + 3);
+            continue;
+        }
+        if bytes[i] != b'<' || !bytes.get(i + 1).is_some_and(u8::is_ascii_alphabetic) {
+            i += 1;
+            continue;
+        }
+
+        // tag name
+        let start = i + 1;
+        i = start;
+        while i < bytes.len() && !is_tag_delimiter(bytes[i]) {
+            i += 1;
+        }
+        let tag = html[start..i].to_ascii_lowercase();
+
+        // attributes
+        while i < bytes.len() && bytes[i] != b'>' {
+            if bytes[i].is_ascii_whitespace() || bytes[i] == b'/' {
+                i += 1;
+                continue;
+            }
+            let name_start = i;
+            while i < bytes.len() && !is_tag_delimiter(bytes[i]) && bytes[i] != b'=' {
+                i += 1;
+            }
+            let name = html[name_start..i].to_ascii_lowercase();
+            if bytes.get(i) != Some(&b'=') {
+                continue;
+            }
+            i += 1;
+            let value = match bytes.get(i) {
+                Some(quote @ (b'"' | b'\'')) => {
+                    let value_start = i + 1;
+                    let value_end = html[value_start..]
+                        .find(*quote as char)
+                        .map_or(bytes.len(), |end| value_start + end);
+                    i = (value_end + 1).min(bytes.len());
+                    value_start..value_end
+                }
+                _ => {
+                    let value_start = i;
+                    while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                        i += 1;
+                    }
+                    value_start..i
+                }
+            };
+            if name == "href" || name == "src" {
+                f(&tag, value);
+            }
+        }
+        i += 1;
+
+        // the contents of scripts and styles are not markup
+        if tag == "script" || tag == "style" {
+            let close = format!("</{tag}");
+            i = html[i..]
+                .to_ascii_lowercase()
+                .find(&close)
+                .map_or(bytes.len(), |end| i + end);
+        }
+    }
+}
+
+/// Returns the `href` and `src` attribute values in `html` and the `<base href>` if there is one.
+pub fn find_links(html: &str) -> (Option<String>, Vec<String>) {
+    let mut base = None;
+    let mut links = Vec::new();
+    scan_links(html, |tag, range| {
+        let value = unescape_html(&html[range]);
+        if tag == "base" {
+            base.get_or_insert(value);
+        } else {
+            links.push(value);
+        }
+    });
+    (base, links)
+}
+
+/// Prefixes the links in `html` that are relative to the site root with `base_url`, so that they
+/// work outside of the site i.e. in a feed reader. Links to a fragment of the same page are kept.
+pub fn absolute_links(html: &str, base_url: &str) -> String {
+    let base_url = escape_xml(base_url.trim_end_matches('/'));
+    let mut out = String::with_capacity(html.len());
+    let mut last = 0;
+    scan_links(html, |_, range| {
+        let link = &html[range.clone()];
+        if link.is_empty() || link.starts_with('#') || is_external(&unescape_html(link)) {
+            return;
+        }
+        out.push_str(&html[last..range.start]);
+        out.push_str(&base_url);
+        out.push('/');
+        out.push_str(link.trim_start_matches('/'));
+        last = range.end;
+    });
+    out.push_str(&html[last..]);
+    out
+}
+
+fn is_tag_delimiter(b: u8) -> bool {
+    b.is_ascii_whitespace() || b == b'>' || b == b'/'
+}
+
+fn unescape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                entity => entity.strip_prefix('#').and_then(|n| {
+                    match n.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => n.parse().ok(),
+                    }
+                    .and_then(char::from_u32)
+                }),
+            }?;
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = s
+            .get(i + 1..i + 3)
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// Whether `link` points elsewhere i.e. `https://example.com`, `//example.com` or `mailto:a@b.c`.
+fn is_external(link: &str) -> bool {
+    link.starts_with("//")
+        || link.split_once(':').is_some_and(|(scheme, _)| {
+            !scheme.is_empty()
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        })
+}
+
+/// Resolves `link` found in the page at `page`, a path relative to the output root, to the path of
+/// the file it points to. Links are relative to `base` when given, like the browser does for
+/// `<base href>`. External links and links to a fragment of the same page resolve to `None`.
+pub fn resolve_link(page: &str, base: Option<&str>, link: &str) -> Option<String> {
+    let link = link.trim();
+    let link = link.split(['#', '?']).next().unwrap_or_default();
+    if link.is_empty() || is_external(link) {
+        return None;
+    }
+
+    let base = match base {
+        Some(base) if is_external(base) => return None,
+        Some(base) if base.starts_with('/') => base.to_string(),
+        Some(base) => format!("/{}", resolve_link(page, None, base).unwrap_or_default()),
+        None => format!("/{page}"),
+    };
+    let joined = if link.starts_with('/') {
+        link.to_string()
+    } else {
+        // relative links replace the last segment of the base
+        let dir = &base[..base.rfind('/').unwrap_or(0) + 1];
+        format!("{dir}{link}")
+    };
+
+    let mut segments = Vec::new();
+    for segment in joined.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(percent_decode(segment)),
+        }
+    }
+    if joined.ends_with('/') || joined.ends_with("/.") || joined.ends_with("/..") {
+        segments.push("index.html".to_string());
+    }
+    Some(segments.join("/"))
+}
+
+/// Returns the links in `html`, the page at `page`, whose resolved paths don't satisfy `exists`.
+pub fn broken_links(page: &str, html: &str, exists: impl Fn(&str) -> bool) -> Vec<String> {
+    let (base, links) = find_links(html);
+    let mut broken = Vec::new();
+    for link in links {
+        let Some(path) = resolve_link(page, base.as_deref(), &link) else {
+            continue;
+        };
+        if !exists(&path) && !broken.contains(&link) {
+            broken.push(link);
+        }
+    }
+    broken
+}
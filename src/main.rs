@@ -2,7 +2,8 @@ use clap::Parser;
 use color_eyre::eyre::Result;
 use std::path::Path;
 use stoic::console::ConsoleHandle;
-use stoic::handlers::{run_build, run_new, run_watch};
+use stoic::handlers::{run_build, run_check_links, run_new, run_post, run_watch};
+use stoic::types::{BuildOptions, ServerOptions};
 
 #[derive(clap::Parser)]
 #[clap(version, about)]
@@ -11,19 +12,100 @@ struct Args {
     command: Command,
 }
 
+#[derive(clap::Args)]
+struct BuildFlags {
+    /// include entities marked as drafts
+    #[arg(long)]
+    drafts: bool,
+    /// include entities dated in the future
+    #[arg(long)]
+    future: bool,
+    /// fail on invalid metadata instead of warning
+    #[arg(long)]
+    strict: bool,
+    /// only remove stale files from the output instead of replacing it
+    #[arg(long)]
+    no_clean: bool,
+    /// check the generated html for broken links
+    #[arg(long)]
+    check_links: bool,
+    /// minify the generated html and the css and js assets
+    #[arg(long)]
+    minify: bool,
+}
+
+impl From<BuildFlags> for BuildOptions {
+    fn from(flags: BuildFlags) -> Self {
+        BuildOptions {
+            drafts: flags.drafts,
+            future: flags.future,
+            strict: flags.strict,
+            clean: !flags.no_clean,
+            check_links: flags.check_links,
+            minify: flags.minify,
+        }
+    }
+}
+
+#[derive(clap::Args)]
+struct ServerFlags {
+    /// address to serve the output on
+    #[arg(long, default_value = "0.0.0.0")]
+    host: String,
+    /// port to serve the output on, the next free port is used if it is taken
+    #[arg(long, default_value_t = 3030)]
+    port: u16,
+    /// open the site in the browser
+    #[arg(long)]
+    open: bool,
+}
+
+impl From<ServerFlags> for ServerOptions {
+    fn from(flags: ServerFlags) -> Self {
+        ServerOptions {
+            host: flags.host,
+            port: flags.port,
+            open: flags.open,
+        }
+    }
+}
+
 #[derive(clap::Subcommand)]
 enum Command {
     New {
         /// directory name
         name: String,
     },
+    /// create a new item in a collection
+    Post {
+        /// site directory
+        site_dir: String,
+        /// collection name, prompted for if omitted
+        collection: Option<String>,
+        /// item title, prompted for if omitted
+        title: Option<String>,
+        /// open the new item in $VISUAL or $EDITOR
+        #[arg(long)]
+        edit: bool,
+    },
     Build {
         input_dir: String,
         output_dir: String,
+        #[command(flatten)]
+        flags: BuildFlags,
     },
     Watch {
         input_dir: String,
         output_dir: String,
+        #[command(flatten)]
+        flags: BuildFlags,
+        #[command(flatten)]
+        server_flags: ServerFlags,
+    },
+    /// check the generated html for broken links
+    CheckLinks {
+        input_dir: String,
+        output_dir: String,
     },
 }
 
@@ -36,24 +118,50 @@ async fn main() -> Result<()> {
     let args = Args::parse();
     if let Err(report) = match args.command {
         Command::New { name } => run_new(&mut console, Path::new(&name)).await,
+        Command::Post {
+            site_dir,
+            collection,
+            title,
+            edit,
+        } => run_post(&mut console, Path::new(&site_dir), collection, title, edit).await,
         Command::Build {
             input_dir,
             output_dir,
+            flags,
         } => {
             run_build(
                 &mut console,
                 Path::new(&input_dir),
                 Path::new(&output_dir),
                 true,
+                &flags.into(),
             )
             .await
         }
         Command::Watch {
             input_dir,
             output_dir,
-        } => run_watch(&mut console, Path::new(&input_dir), Path::new(&output_dir)).await,
+            flags,
+            server_flags,
+        } => {
+            run_watch(
+                &mut console,
+                Path::new(&input_dir),
+                Path::new(&output_dir),
+                &flags.into(),
+                &server_flags.into(),
+            )
+            .await
+        }
+        Command::CheckLinks {
+            input_dir,
+            output_dir,
+        } => run_check_links(&mut console, Path::new(&input_dir), Path::new(&output_dir)).await,
     } {
         console.log_report(report)?;
+        // a failed command exits non-zero so that i.e. `check-links` can gate a CI job
+        drop(console);
+        std::process::exit(1);
     }
     Ok(())
 }
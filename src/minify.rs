@@ -0,0 +1,57 @@
+use color_eyre::eyre::{eyre, Result};
+use lightningcss::stylesheet::{MinifyOptions, ParserOptions, PrinterOptions, StyleSheet};
+use minify_html::Cfg;
+use minify_js::{Session, TopLevelMode};
+use std::path::Path;
+
+pub fn minify_html(html: &str) -> String {
+    let cfg = Cfg {
+        do_not_minify_doctype: true,
+        ensure_spec_compliant_unquoted_attribute_values: true,
+        keep_spaces_between_attributes: true,
+        minify_css: true,
+        minify_js: true,
+        ..Cfg::default()
+    };
+    String::from_utf8_lossy(&minify_html::minify(html.as_bytes(), &cfg)).to_string()
+}
+
+pub fn minify_css(css: &str) -> Result<String> {
+    let mut stylesheet =
+        StyleSheet::parse(css, ParserOptions::default()).map_err(|e| eyre!(e.to_string()))?;
+    stylesheet
+        .minify(MinifyOptions::default())
+        .map_err(|e| eyre!(e.to_string()))?;
+    let out = stylesheet
+        .to_css(PrinterOptions {
+            minify: true,
+            ..PrinterOptions::default()
+        })
+        .map_err(|e| eyre!(e.to_string()))?;
+    Ok(out.code)
+}
+
+pub fn minify_js(js: &[u8]) -> Result<Vec<u8>> {
+    let session = Session::new();
+    let mut out = Vec::new();
+    // scripts are not modules so top level names are left as is
+    minify_js::minify(&session, TopLevelMode::Global, js, &mut out)
+        .map_err(|e| eyre!(e.to_string()))?;
+    Ok(out)
+}
+
+pub fn is_minifiable(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "css" || e == "js")
+}
+
+/// Minifies a CSS or JS asset, leaving other assets as they are.
+pub fn minify_asset(path: &Path, contents: Vec<u8>) -> Result<Vec<u8>> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("css") => {
+            let css = String::from_utf8(contents).map_err(|_| eyre!("Invalid UTF-8"))?;
+            Ok(minify_css(&css)?.into_bytes())
+        }
+        Some("js") => minify_js(&contents),
+        _ => Ok(contents),
+    }
+}
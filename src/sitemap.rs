@@ -0,0 +1,34 @@
+use crate::utils::escape_xml;
+
+pub struct SitemapEntry {
+    pub path: String,
+    pub lastmod: Option<String>,
+}
+
+pub fn sitemap(base_url: &str, entries: &[SitemapEntry]) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    let mut out = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    out.push_str(r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#);
+    for entry in entries {
+        // index.html files are served as their directory
+        let path = entry.path.strip_suffix("index.html").unwrap_or(&entry.path);
+        out.push_str("<url>");
+        out.push_str(&format!(
+            "<loc>{}</loc>",
+            escape_xml(&format!("{base_url}/{path}"))
+        ));
+        if let Some(lastmod) = &entry.lastmod {
+            out.push_str(&format!("<lastmod>{}</lastmod>", escape_xml(lastmod)));
+        }
+        out.push_str("</url>");
+    }
+    out.push_str("</urlset>\n");
+    out
+}
+
+pub fn robots_txt(base_url: &str) -> String {
+    format!(
+        "User-agent: *\nAllow: /\n\nSitemap: {}/sitemap.xml\n",
+        base_url.trim_end_matches('/')
+    )
+}
@@ -2,14 +2,15 @@ use strum_macros::{Display, EnumIter};
 
 const BASE_TEMPLATE: &str = r#"
 <!doctype html>
-<html lang="en">
+<html lang="{{#if site.language}}{{site.language}}{{else}}en{{/if}}">
   <head>
     <meta charset="utf-8">
     <meta name="viewport" content="width=device-width,initial-scale=1">
     <title>{{#if head_title}}{{head_title}}{{else}}{{title}}{{/if}}</title>
     <base href="/">
-    <link rel="stylesheet" href="assets/style.css">
-    <script type="text/javascript" src="assets/script.js" async defer></script>
+    <link rel="stylesheet" href="{{asset "style.css"}}">
+    {{#if site.highlight}}<link rel="stylesheet" href="{{asset "highlight.css"}}">{{/if}}
+    <script type="text/javascript" src="{{asset "script.js"}}" async defer></script>
   </head>
   <body>
     <div id="container">
@@ -53,12 +54,19 @@ const POSTS_TEMPLATE: &str = r#"
     {{#each entities}}
     <li class="posts-list-item">
       <a href="posts/{{this.filename}}">
-        <div class="posts-list-item-title">{{this.title}}</div>
-        <div class="posts-list-item-time">{{this.created_at}}</div>
+        <div class="posts-list-item-title">{{this.title}}{{#if this.draft}} (draft){{/if}}{{#if this.scheduled}} (scheduled){{/if}}</div>
+        <div class="posts-list-item-time">{{this.created_at}} · {{this.reading_time}} min read</div>
       </a>
     </li>
     {{/each}}
   </ul>
+  {{#if (or pagination.previous pagination.next)}}
+  <nav class="pagination">
+    {{#if pagination.previous}}<a href="{{pagination.previous}}">Previous</a>{{/if}}
+    <span>{{pagination.current}} / {{pagination.total}}</span>
+    {{#if pagination.next}}<a href="{{pagination.next}}">Next</a>{{/if}}
+  </nav>
+  {{/if}}
 </section>
 {{/inline}}
 {{> base}}
@@ -68,7 +76,55 @@ const POST_TEMPLATE: &str = r#"
 {{#*inline "filling"}}
 {{> nav}}
 <section>
+  {{#if draft}}<p class="draft">Draft</p>{{/if}}
+  {{#if scheduled}}<p class="scheduled">Scheduled</p>{{/if}}
   {{{contents}}}
+  {{#if taxonomies.tags}}
+  <ul class="tags-list">
+    {{#each taxonomies.tags}}
+    <li><a href="{{this.link}}">{{this.name}}</a></li>
+    {{/each}}
+  </ul>
+  {{/if}}
+  {{#if (or previous next)}}
+  <nav class="pagination">
+    {{#if next}}<a href="{{next.link}}">Newer: {{next.title}}</a>{{/if}}
+    {{#if previous}}<a href="{{previous.link}}">Older: {{previous.title}}</a>{{/if}}
+  </nav>
+  {{/if}}
+</section>
+{{/inline}}
+{{> base}}
+"#;
+
+const TAGS_TEMPLATE: &str = r#"
+{{#*inline "filling"}}
+{{> nav}}
+<section>
+  <ul class="tags-list">
+    {{#each terms}}
+    <li><a href="{{this.link}}">{{this.name}}</a> ({{this.count}})</li>
+    {{/each}}
+  </ul>
+</section>
+{{/inline}}
+{{> base}}
+"#;
+
+const TAG_TEMPLATE: &str = r#"
+{{#*inline "filling"}}
+{{> nav}}
+<section>
+  <ul id="posts-list">
+    {{#each entities}}
+    <li class="posts-list-item">
+      <a href="{{this.link}}">
+        <div class="posts-list-item-title">{{this.title}}</div>
+        <div class="posts-list-item-time">{{this.created_at}} · {{this.reading_time}} min read</div>
+      </a>
+    </li>
+    {{/each}}
+  </ul>
 </section>
 {{/inline}}
 {{> base}}
@@ -92,6 +148,8 @@ pub enum TemplateName {
     Page,
     Posts,
     Post,
+    Tags,
+    Tag,
     Nav,
 }
 
@@ -103,6 +161,8 @@ impl TemplateName {
             TemplateName::Page => PAGE_TEMPLATE,
             TemplateName::Posts => POSTS_TEMPLATE,
             TemplateName::Post => POST_TEMPLATE,
+            TemplateName::Tags => TAGS_TEMPLATE,
+            TemplateName::Tag => TAG_TEMPLATE,
             TemplateName::Nav => NAV_TEMPLATE,
         }
     }
@@ -1,20 +1,337 @@
+use crate::errors::MetadataError;
+use crate::utils::toml_to_json;
+use heck::ToTitleCase;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use toml_datetime::Datetime;
 
+fn serialize_toml_table<S: serde::Serializer>(
+    table: &toml::Table,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    toml_to_json(&toml::Value::Table(table.clone())).serialize(serializer)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SiteConfig {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub base_url: Option<String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    pub feeds: FeedsConfig,
+    pub taxonomies: Vec<String>,
+    pub term_templates: BTreeMap<String, String>,
+    pub paginate: Option<usize>,
+    pub highlight: Option<HighlightConfig>,
+    pub heading_anchors: bool,
+    pub preserve: Vec<String>,
+    pub theme: Option<String>,
+    pub fingerprint_assets: bool,
+    pub minify: bool,
+    #[serde(serialize_with = "serialize_toml_table")]
+    pub extra: toml::Table,
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        SiteConfig {
+            title: None,
+            author: None,
+            base_url: None,
+            description: None,
+            language: None,
+            feeds: FeedsConfig::default(),
+            taxonomies: vec!["tags".to_string()],
+            term_templates: BTreeMap::new(),
+            paginate: None,
+            highlight: None,
+            heading_anchors: false,
+            preserve: Vec::new(),
+            theme: None,
+            fingerprint_assets: false,
+            minify: false,
+            extra: toml::Table::new(),
+        }
+    }
+}
+
+/// Patterns of files that are always kept in the output dir in addition to `preserve`, so that a
+/// gh-pages checkout and its custom domain survive a rebuild.
+pub const ALWAYS_PRESERVED: [&str; 2] = [".git", "CNAME"];
+
+impl SiteConfig {
+    pub fn preserve_patterns(&self) -> Vec<String> {
+        ALWAYS_PRESERVED
+            .iter()
+            .map(|p| p.to_string())
+            .chain(self.preserve.iter().cloned())
+            .collect()
+    }
+
+    /// The template of the term pages of `taxonomy`, which defaults to the taxonomy name without
+    /// a trailing `s` i.e. `tag` for `tags`.
+    pub fn term_template<'a>(&'a self, taxonomy: &'a str) -> &'a str {
+        match self.term_templates.get(taxonomy) {
+            Some(template) => template,
+            None => taxonomy.strip_suffix("s").unwrap_or(taxonomy),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FeedsConfig {
+    pub enabled: bool,
+    pub exclude: Vec<String>,
+}
+
+impl Default for FeedsConfig {
+    fn default() -> Self {
+        FeedsConfig {
+            enabled: true,
+            exclude: Vec::new(),
+        }
+    }
+}
+
+impl FeedsConfig {
+    pub fn is_enabled_for(&self, collection_name: &str) -> bool {
+        self.enabled && !self.exclude.iter().any(|n| n == collection_name)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HighlightConfig {
+    pub theme: String,
+    pub line_numbers: bool,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        HighlightConfig {
+            theme: "InspiredGitHub".to_string(),
+            line_numbers: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    #[default]
+    Date,
+    Title,
+    Filename,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// The optional `_collection.toml` in a collection directory.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CollectionConfig {
+    pub title: Option<String>,
+    pub item_template: Option<String>,
+    pub index_template: Option<String>,
+    pub sort_by: SortBy,
+    pub sort_order: SortOrder,
+    pub path: Option<String>,
+    pub render_index: bool,
+}
+
+impl Default for CollectionConfig {
+    fn default() -> Self {
+        CollectionConfig {
+            title: None,
+            item_template: None,
+            index_template: None,
+            sort_by: SortBy::default(),
+            sort_order: SortOrder::default(),
+            path: None,
+            render_index: true,
+        }
+    }
+}
+
+/// A collection directory with the defaults of its config filled in.
+#[derive(Debug, Clone)]
+pub struct Collection {
+    pub name: String,
+    pub title: String,
+    pub item_template: String,
+    pub index_template: String,
+    pub sort_by: SortBy,
+    pub sort_order: SortOrder,
+    /// the output directory relative to the output root
+    pub path: String,
+    pub render_index: bool,
+}
+
+impl Collection {
+    pub fn new(name: &str, config: CollectionConfig) -> Self {
+        Collection {
+            title: config.title.unwrap_or(name.to_title_case()),
+            item_template: config
+                .item_template
+                .unwrap_or(name.strip_suffix("s").unwrap_or(name).to_string()),
+            index_template: config.index_template.unwrap_or(name.to_string()),
+            sort_by: config.sort_by,
+            sort_order: config.sort_order,
+            path: config
+                .path
+                .map(|p| p.trim_matches('/').to_string())
+                .filter(|p| !p.is_empty())
+                .unwrap_or(name.to_string()),
+            render_index: config.render_index,
+            name: name.to_string(),
+        }
+    }
+
+    pub fn sort(&self, entities: &mut [Entity]) {
+        entities.sort_by(|a, b| {
+            let ordering = match self.sort_by {
+                SortBy::Date => a.created_at_iso.cmp(&b.created_at_iso),
+                SortBy::Title => a.title.cmp(&b.title),
+                SortBy::Filename => a.source.cmp(&b.source),
+            };
+            match self.sort_order {
+                SortOrder::Asc => ordering,
+                SortOrder::Desc => ordering.reverse(),
+            }
+        });
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct BuildOptions {
+    pub drafts: bool,
+    pub future: bool,
+    pub strict: bool,
+    pub clean: bool,
+    pub check_links: bool,
+    pub minify: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    pub host: String,
+    pub port: u16,
+    pub open: bool,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct EntityMetadata {
     pub slug: Option<String>,
     pub shortname: Option<String>,
     pub date: Option<Datetime>,
     pub head_title: Option<String>,
+    pub description: Option<String>,
+    pub draft: Option<bool>,
+    pub toc: Option<bool>,
+    pub tags: Option<Vec<String>>,
+    pub taxonomies: Option<BTreeMap<String, Vec<String>>>,
+    #[serde(flatten)]
+    pub extra: toml::Table,
+}
+
+impl EntityMetadata {
+    pub fn terms(&self, taxonomy: &str) -> Vec<String> {
+        let mut terms = self
+            .taxonomies
+            .as_ref()
+            .and_then(|t| t.get(taxonomy).cloned())
+            .unwrap_or_default();
+        if taxonomy == "tags" {
+            terms.extend(self.tags.iter().flatten().cloned());
+        }
+        terms
+    }
+}
+
+/// A markdown file rendered to html.
+pub struct Document {
+    pub metadata: Option<EntityMetadata>,
+    /// set when the metadata fails to parse, without a path
+    pub metadata_error: Option<MetadataError>,
+    pub title: String,
+    pub contents: String,
+    pub toc: Vec<TocEntry>,
+    pub excerpt: Option<String>,
+    pub word_count: usize,
+}
+
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct TocEntry {
+    pub level: u8,
+    pub id: String,
+    pub title: String,
+    pub children: Vec<TocEntry>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct Term {
+    pub name: String,
+    pub slug: String,
+    pub link: String,
 }
 
 #[derive(Serialize)]
+pub struct TermSummary<'a> {
+    #[serde(flatten)]
+    pub term: &'a Term,
+    pub count: usize,
+}
+
+#[derive(Serialize, Clone)]
 pub struct Entity {
+    #[serde(skip)]
+    pub source: String,
     pub filename: String,
+    pub link: String,
     pub title: String,
     pub created_at_iso: String,
     pub created_at: String,
+    #[serde(skip)]
+    pub shortname: String,
+    #[serde(skip)]
+    pub head_title: String,
+    pub contents: String,
+    #[serde(skip)]
+    pub toc: Vec<TocEntry>,
+    pub taxonomies: BTreeMap<String, Vec<Term>>,
+    pub draft: bool,
+    pub scheduled: bool,
+    pub meta: serde_json::Value,
+    pub excerpt: Option<String>,
+    pub word_count: usize,
+    pub reading_time: usize,
+}
+
+/// The fields of a neighbouring entity that are passed to item templates.
+#[derive(Serialize)]
+pub struct EntitySummary<'a> {
+    pub title: &'a str,
+    pub link: &'a str,
+    pub created_at: &'a str,
+}
+
+impl<'a> From<&'a Entity> for EntitySummary<'a> {
+    fn from(entity: &'a Entity) -> Self {
+        EntitySummary {
+            title: &entity.title,
+            link: &entity.link,
+            created_at: &entity.created_at,
+        }
+    }
 }
 
 #[derive(Serialize, Clone)]
@@ -23,17 +340,45 @@ pub struct Breadcrumb<'a> {
     pub link: &'a str,
 }
 
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Pagination {
+    pub current: usize,
+    pub total: usize,
+    pub previous: Option<String>,
+    pub next: Option<String>,
+}
+
 #[derive(Serialize)]
 pub struct EntitiesArgs<'a> {
+    pub site: &'a SiteConfig,
+    pub path: &'a [Breadcrumb<'a>],
+    pub title: &'a str,
+    pub entities: &'a [Entity],
+    pub pagination: &'a Pagination,
+}
+
+#[derive(Serialize)]
+pub struct TermsArgs<'a> {
+    pub site: &'a SiteConfig,
     pub path: &'a [Breadcrumb<'a>],
     pub title: &'a str,
-    pub entities: Vec<Entity>,
+    pub terms: &'a [TermSummary<'a>],
 }
 
 #[derive(Serialize)]
 pub struct EntityArgs<'a> {
+    pub site: &'a SiteConfig,
     pub path: &'a [Breadcrumb<'a>],
     pub title: &'a str,
     pub head_title: &'a str,
     pub contents: &'a str,
+    pub taxonomies: &'a BTreeMap<String, Vec<Term>>,
+    pub draft: bool,
+    pub scheduled: bool,
+    pub meta: &'a serde_json::Value,
+    pub toc: &'a [TocEntry],
+    pub word_count: usize,
+    pub reading_time: usize,
+    pub previous: Option<EntitySummary<'a>>,
+    pub next: Option<EntitySummary<'a>>,
 }
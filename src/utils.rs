@@ -1,14 +1,26 @@
-use crate::types::EntityMetadata;
-use color_eyre::eyre::Result;
-use pulldown_cmark::{html, Event, HeadingLevel, Options, Parser, Tag};
+use crate::changes::COLLECTION_CONFIG_FILENAME;
+use crate::errors::{ConfigError, IOError, MetadataError};
+use crate::highlight::{highlight_code, is_supported};
+use crate::types::{
+    Collection, Document, Entity, EntityMetadata, HighlightConfig, Pagination, SiteConfig, TocEntry,
+};
+use color_eyre::eyre::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use pulldown_cmark::{
+    html, CodeBlockKind, Event, HeadingLevel, MetadataBlockKind, Options, Parser, Tag, TagEnd,
+};
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
 use std::fs::Metadata;
 use std::io;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use tokio::fs::{
-    copy, create_dir_all, read_dir, read_to_string, remove_dir_all, remove_file, File,
+    copy, create_dir_all, metadata, read_dir, read_to_string, remove_dir_all, remove_file, File,
 };
 use walkdir::WalkDir;
 
+const WORDS_PER_MINUTE: usize = 200;
+
 // IO Actions
 pub fn get_files_in_dir_recursive(path: &Path) -> Vec<PathBuf> {
     WalkDir::new(path)
@@ -28,6 +40,61 @@ pub fn get_files_in_dir_recursive(path: &Path) -> Vec<PathBuf> {
         .collect::<Vec<_>>()
 }
 
+/// Returns the paths under `path` that match `preserve`, without descending into matched dirs.
+pub fn get_preserved_paths(path: &Path, preserve: &GlobSet) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let mut entries = WalkDir::new(path).min_depth(1).into_iter();
+    while let Some(entry) = entries.next() {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        let Ok(relative_path) = entry.path().strip_prefix(path) else {
+            continue;
+        };
+        if preserve.is_match(relative_path) {
+            paths.push(relative_path.to_path_buf());
+            if entry.file_type().is_dir() {
+                entries.skip_current_dir();
+            }
+        }
+    }
+    paths
+}
+
+/// Returns the files and dirs under `path` that are neither in `built_dir` nor preserved.
+/// Dirs come after their contents so they can be removed once emptied.
+pub fn get_stale_paths(path: &Path, built_dir: &Path, preserve: &GlobSet) -> Vec<(PathBuf, bool)> {
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    let mut entries = WalkDir::new(path).min_depth(1).into_iter();
+    while let Some(entry) = entries.next() {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        let Ok(relative_path) = entry.path().strip_prefix(path) else {
+            continue;
+        };
+        let is_dir = entry.file_type().is_dir();
+        if preserve.is_match(relative_path) {
+            if is_dir {
+                entries.skip_current_dir();
+            }
+            continue;
+        }
+        let built_path = built_dir.join(relative_path);
+        if is_dir {
+            if !built_path.is_dir() {
+                dirs.push((entry.path().to_path_buf(), true));
+            }
+        } else if !built_path.is_file() {
+            files.push((entry.path().to_path_buf(), false));
+        }
+    }
+    dirs.reverse();
+    files.extend(dirs);
+    files
+}
+
 pub async fn get_entries_in_dir(
     path: &Path,
 ) -> Result<Vec<(String, Metadata, PathBuf)>, io::Error> {
@@ -49,6 +116,14 @@ pub async fn remove_path(metadata: Metadata, path: PathBuf) -> Result<(), io::Er
     }
 }
 
+pub async fn remove_if_exists(path: &Path) -> Result<(), io::Error> {
+    match metadata(path).await {
+        Ok(metadata) => remove_path(metadata, path.to_path_buf()).await,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
 pub async fn read_template(name: String, dir: &Path) -> Result<(String, String), io::Error> {
     Ok((
         name.strip_suffix(".hbs").unwrap_or(&name).to_string(),
@@ -61,6 +136,24 @@ pub async fn read_template(name: String, dir: &Path) -> Result<(String, String),
     ))
 }
 
+async fn read_config<T: DeserializeOwned + Default>(path: PathBuf) -> Result<T> {
+    let config_str = match read_to_string(&path).await {
+        Ok(config_str) => config_str,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(T::default()),
+        Err(e) => return Err(e).wrap_err(IOError::Read { path }),
+    };
+    toml::from_str(&config_str).wrap_err(ConfigError { path })
+}
+
+pub async fn read_site_config(dir: &Path) -> Result<SiteConfig> {
+    read_config(dir.join("stoic.toml")).await
+}
+
+pub async fn read_collection(input_dir: &Path, name: &str) -> Result<Collection> {
+    let config = read_config(input_dir.join(name).join(COLLECTION_CONFIG_FILENAME)).await?;
+    Ok(Collection::new(name, config))
+}
+
 pub async fn copy_file(input_path: PathBuf, output_path: PathBuf) -> Result<()> {
     create_dir_all(output_path.parent().unwrap()).await?;
     // cannot rely on copy-on-write due to this issue: https://github.com/notify-rs/notify/issues/465
@@ -70,28 +163,389 @@ pub async fn copy_file(input_path: PathBuf, output_path: PathBuf) -> Result<()>
 }
 
 // Pure Actions
-pub fn md_to_html(md_str: &str) -> (Option<EntityMetadata>, String, String) {
+pub fn slugify(s: &str) -> String {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The slug of a taxonomy term. Terms without any alphanumeric characters i.e. `!!!` get one
+/// from a hash of the name so that they don't end up in the taxonomy index dir.
+pub fn term_slug(name: &str) -> String {
+    let slug = slugify(name);
+    if !slug.is_empty() {
+        return slug;
+    }
+    format!("term-{}", &blake3::hash(name.as_bytes()).to_hex()[..8])
+}
+
+/// The filename of a new collection item i.e. `2023-03-25-hello-world.md`.
+pub fn entity_filename(date: &str, title: &str) -> Option<String> {
+    let slug = slugify(title);
+    (!slug.is_empty()).then(|| format!("{date}-{slug}.md"))
+}
+
+pub fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+pub fn page_path(page: usize) -> String {
+    if page == 1 {
+        String::new()
+    } else {
+        format!("page/{page}/")
+    }
+}
+
+pub fn paginate<'a>(
+    entities: &'a [Entity],
+    page_size: Option<usize>,
+    link: &str,
+) -> Vec<(Pagination, &'a [Entity])> {
+    let page_size = page_size
+        .filter(|n| *n > 0)
+        .unwrap_or(entities.len())
+        .max(1);
+    let pages = if entities.is_empty() {
+        vec![entities]
+    } else {
+        entities.chunks(page_size).collect()
+    };
+    let total = pages.len();
+    pages
+        .into_iter()
+        .enumerate()
+        .map(|(i, page_entities)| {
+            let current = i + 1;
+            let pagination = Pagination {
+                current,
+                total,
+                previous: (current > 1).then(|| format!("{link}/{}", page_path(current - 1))),
+                next: (current < total).then(|| format!("{link}/{}", page_path(current + 1))),
+            };
+            (pagination, page_entities)
+        })
+        .collect()
+}
+
+/// Returns the entities right before and after `entities[i]` by date, which are its older and
+/// newer neighbours whatever order the collection is sorted in.
+pub fn neighbours(entities: &[Entity], i: usize) -> (Option<&Entity>, Option<&Entity>) {
+    // entities with the same date are ordered by source so that every one has neighbours
+    fn key(e: &Entity) -> (&str, &str) {
+        (&e.created_at_iso, &e.source)
+    }
+    let entity = key(&entities[i]);
+    let previous = entities
+        .iter()
+        .filter(|e| key(e) < entity)
+        .max_by_key(|e| key(e));
+    let next = entities
+        .iter()
+        .filter(|e| key(e) > entity)
+        .min_by_key(|e| key(e));
+    (previous, next)
+}
+
+/// Returns the sources of the neighbours of the entity from `source`, which have to be rendered
+/// again when it changes because they link to it.
+pub fn neighbour_sources(entities: &[Entity], source: &str) -> Vec<String> {
+    let Some(i) = entities.iter().position(|e| e.source == source) else {
+        return Vec::new();
+    };
+    let (previous, next) = neighbours(entities, i);
+    previous
+        .into_iter()
+        .chain(next)
+        .map(|e| e.source.clone())
+        .collect()
+}
+
+/// Serializes TOML as JSON for templates, which would otherwise see datetimes as objects.
+pub fn toml_to_json(value: &toml::Value) -> serde_json::Value {
+    match value {
+        toml::Value::String(s) => serde_json::Value::from(s.as_str()),
+        toml::Value::Integer(i) => serde_json::Value::from(*i),
+        toml::Value::Float(f) => serde_json::Value::from(*f),
+        toml::Value::Boolean(b) => serde_json::Value::from(*b),
+        toml::Value::Datetime(dt) => serde_json::Value::from(dt.to_string()),
+        toml::Value::Array(a) => a.iter().map(toml_to_json).collect(),
+        toml::Value::Table(t) => t
+            .iter()
+            .map(|(k, v)| (k.clone(), toml_to_json(v)))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+    }
+}
+
+/// Nests a flat list of headings so that each heading is a child of the closest preceding
+/// heading with a lower level.
+pub fn nest_toc(headings: Vec<TocEntry>) -> Vec<TocEntry> {
+    let mut toc: Vec<TocEntry> = Vec::new();
+    for heading in headings {
+        let mut siblings = &mut toc;
+        while siblings
+            .last()
+            .is_some_and(|parent| parent.level < heading.level)
+        {
+            siblings = &mut siblings.last_mut().unwrap().children;
+        }
+        siblings.push(heading);
+    }
+    toc
+}
+
+/// Gives every heading an id, keeping explicit `{#id}`s and generating unique ones from the
+/// heading text for the rest.
+fn heading_ids(headings: &[(HeadingLevel, Option<String>, String)]) -> Vec<String> {
+    let mut used = headings
+        .iter()
+        .filter_map(|(_, id, _)| id.clone())
+        .collect::<HashSet<_>>();
+    headings
+        .iter()
+        .map(|(_, id, text)| {
+            if let Some(id) = id {
+                return id.clone();
+            }
+            let base = Some(slugify(text))
+                .filter(|s| !s.is_empty())
+                .unwrap_or("section".to_string());
+            let mut id = base.clone();
+            for i in 1.. {
+                if !used.contains(&id) {
+                    break;
+                }
+                id = format!("{base}-{i}");
+            }
+            used.insert(id.clone());
+            id
+        })
+        .collect()
+}
+
+/// Converts YAML into TOML so that both front matter formats deserialize the same way.
+/// YAML has no date type so strings that are valid TOML datetimes become datetimes.
+pub fn yaml_to_toml(value: serde_yaml_ng::Value) -> Option<toml::Value> {
+    use serde_yaml_ng::Value as Yaml;
+    match value {
+        Yaml::Null => None,
+        Yaml::Bool(b) => Some(toml::Value::Boolean(b)),
+        Yaml::Number(n) => n
+            .as_i64()
+            .map(toml::Value::Integer)
+            .or(n.as_f64().map(toml::Value::Float)),
+        Yaml::String(s) => Some(
+            s.parse::<toml_datetime::Datetime>()
+                .map(toml::Value::Datetime)
+                .unwrap_or(toml::Value::String(s)),
+        ),
+        Yaml::Sequence(seq) => Some(toml::Value::Array(
+            seq.into_iter().filter_map(yaml_to_toml).collect(),
+        )),
+        Yaml::Mapping(map) => Some(toml::Value::Table(
+            map.into_iter()
+                .filter_map(|(k, v)| Some((k.as_str()?.to_string(), yaml_to_toml(v)?)))
+                .collect(),
+        )),
+        Yaml::Tagged(tagged) => yaml_to_toml(tagged.value),
+    }
+}
+
+/// Returns the 1-based line and column of the byte `offset` in `s`.
+fn line_column(s: &str, offset: usize) -> (usize, usize) {
+    let before = &s[..offset.min(s.len())];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Returns the path of keys of the `key = value` line at byte `offset` of `toml_str`, as written by
+/// `toml::to_string` with a `[table]` header for each nested table.
+fn toml_key_path(toml_str: &str, offset: usize) -> Option<Vec<String>> {
+    let line_start = toml_str[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = toml_str[line_start..].lines().next()?;
+    let (key, _) = line.split_once(" = ")?;
+    let mut keys = toml_str[..line_start]
+        .lines()
+        .rev()
+        .find_map(|l| l.strip_prefix('[')?.strip_suffix(']'))
+        .map(|table| {
+            table
+                .split('.')
+                .map(|k| k.trim_matches('"').to_string())
+                .collect()
+        })
+        .unwrap_or(Vec::new());
+    keys.push(key.trim().trim_matches('"').to_string());
+    Some(keys)
+}
+
+/// Returns the byte offset of the value of the nested block mapping `keys` in the YAML `source`,
+/// or of the last key when its value is on the following lines.
+fn yaml_key_offset(source: &str, keys: &[String]) -> Option<usize> {
+    let mut start = 0;
+    let mut parent_indent = None;
+    let mut offset = None;
+    for key in keys {
+        offset = None;
+        let mut line_start = start;
+        for line in source[start..].split_inclusive('\n') {
+            let content = line.trim_start();
+            let indent = line.len() - content.len();
+            let is_blank = content.trim().is_empty() || content.starts_with('#');
+            if !is_blank && parent_indent.is_some_and(|parent| indent <= parent) {
+                break;
+            }
+            let value = content
+                .strip_prefix(key.as_str())
+                .and_then(|rest| rest.trim_start().strip_prefix(':'))
+                .map(str::trim_start);
+            if let Some(value) = value.filter(|_| parent_indent.is_some() || indent == 0) {
+                offset = Some(if value.trim().is_empty() || value.starts_with('#') {
+                    line_start + indent
+                } else {
+                    line_start + line.len() - value.len()
+                });
+                start = line_start + line.len();
+                parent_indent = Some(indent);
+                break;
+            }
+            line_start += line.len();
+        }
+        offset?;
+    }
+    offset
+}
+
+/// Parses the metadata `source` which starts at byte `offset` of `md_str` so that errors
+/// point into the markdown file.
+fn parse_metadata(
+    md_str: &str,
+    kind: Option<MetadataBlockKind>,
+    source: &str,
+    offset: usize,
+) -> Result<EntityMetadata, MetadataError> {
+    let error = |index: Option<usize>, message: &str| {
+        let (line, column) = line_column(md_str, offset + index.unwrap_or(0));
+        MetadataError {
+            path: PathBuf::new(),
+            line,
+            column,
+            message: message.to_string(),
+        }
+    };
+    match kind {
+        Some(MetadataBlockKind::YamlStyle) => {
+            let value = serde_yaml_ng::from_str(source).map_err(|e| {
+                // the location is reported separately
+                let message = e.to_string();
+                let message = message.split(" at line ").next().unwrap_or_default();
+                error(e.location().map(|l| l.index()), message)
+            })?;
+            // deserializing a toml::Value directly loses datetimes so it is round tripped
+            let toml_str = match yaml_to_toml(value) {
+                None => String::new(),
+                Some(table @ toml::Value::Table(_)) => {
+                    toml::to_string(&table).map_err(|e| error(None, &e.to_string()))?
+                }
+                Some(_) => return Err(error(None, "expected a mapping of keys to values")),
+            };
+            toml::from_str(&toml_str).map_err(|e| {
+                // the error points into the converted toml so it is mapped back to the yaml key
+                let index = e
+                    .span()
+                    .and_then(|span| toml_key_path(&toml_str, span.start))
+                    .and_then(|keys| yaml_key_offset(source, &keys));
+                error(index, e.message())
+            })
+        }
+        _ => toml::from_str(source).map_err(|e| error(e.span().map(|s| s.start), e.message())),
+    }
+}
+
+/// Returns the kind and source of the YAML (`---`) or TOML (`+++`) front matter at the very top of
+/// `md_str`, with the offsets of its source and of the markdown after it.
+fn front_matter(md_str: &str) -> Option<(MetadataBlockKind, &str, usize, usize)> {
+    let first_line_end = md_str.find('\n')?;
+    let kind = match md_str[..first_line_end].trim_end() {
+        "---" => MetadataBlockKind::YamlStyle,
+        "+++" => MetadataBlockKind::PlusesStyle,
+        _ => return None,
+    };
+    let source_start = first_line_end + 1;
+    let mut line_start = source_start;
+    while line_start < md_str.len() {
+        let line_end = md_str[line_start..]
+            .find('\n')
+            .map_or(md_str.len(), |i| line_start + i);
+        let is_end = matches!(
+            (kind, md_str[line_start..line_end].trim_end()),
+            (MetadataBlockKind::YamlStyle, "---" | "...") | (MetadataBlockKind::PlusesStyle, "+++")
+        );
+        if is_end {
+            let source = &md_str[source_start..line_start];
+            return Some((kind, source, source_start, (line_end + 1).min(md_str.len())));
+        }
+        line_start = line_end + 1;
+    }
+    None
+}
+
+pub fn md_to_html(
+    md_str: &str,
+    highlight: Option<&HighlightConfig>,
+    heading_anchors: bool,
+) -> Document {
     let mut options = Options::empty();
     options.insert(Options::ENABLE_FOOTNOTES);
     options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
     options.insert(Options::ENABLE_STRIKETHROUGH);
-    let mut parser = Parser::new_ext(md_str, options);
-    let mut inside_header = false;
+    // front matter is split off by hand since the parser takes `---` blocks anywhere for metadata
+    let front_matter = front_matter(md_str);
+    let body_offset = front_matter.map_or(0, |(.., body_offset)| body_offset);
+    let body = &md_str[body_offset..];
+    let mut parser = Parser::new_ext(body, options);
+    let mut heading: Option<(HeadingLevel, Option<String>, String)> = None;
+    let mut headings = Vec::new();
     let mut title = String::new();
     let mut inside_metadata = false;
     let mut metadata_str = String::new();
-    for event in parser {
+    let mut plain_text = String::new();
+    let mut metadata_offset = 0;
+    for (event, range) in parser.into_offset_iter() {
+        match &event {
+            Event::Text(text) | Event::Code(text) => plain_text.push_str(text),
+            // words may be split across inline events so only blocks and breaks separate them
+            Event::End(
+                TagEnd::Paragraph
+                | TagEnd::Heading(_)
+                | TagEnd::Item
+                | TagEnd::CodeBlock
+                | TagEnd::TableCell,
+            )
+            | Event::SoftBreak
+            | Event::HardBreak => plain_text.push(' '),
+            _ => (),
+        }
         match event {
-            Event::Start(Tag::Heading {
-                level: HeadingLevel::H1,
-                ..
-            }) => inside_header = true,
-            Event::Text(text) => {
-                if inside_header {
-                    title = text.to_string();
-                    break;
+            Event::Start(Tag::Heading { level, id, .. }) => {
+                heading = Some((level, id.map(|id| id.to_string()), String::new()));
+            }
+            Event::Text(text) | Event::Code(text) if heading.is_some() => {
+                if let Some((_, _, heading_text)) = heading.as_mut() {
+                    heading_text.push_str(&text);
                 }
             }
+            Event::End(TagEnd::Heading(_)) => headings.extend(heading.take()),
             Event::Html(html_text) => {
                 if !inside_metadata {
                     if html_text.to_string().trim() == "<!--metadata" {
@@ -104,15 +558,241 @@ pub fn md_to_html(md_str: &str) -> (Option<EntityMetadata>, String, String) {
                     inside_metadata = false;
                     continue;
                 }
+                if metadata_str.is_empty() {
+                    metadata_offset = body_offset + range.start;
+                }
                 metadata_str.push_str(html_text.to_string().as_ref());
             }
             _ => (),
         };
     }
 
-    let metadata: Option<EntityMetadata> = toml::from_str(metadata_str.as_ref()).ok();
-    parser = Parser::new_ext(md_str, options);
+    let (metadata, metadata_error) = match front_matter {
+        Some((kind, source, offset, _)) => parse_metadata(md_str, Some(kind), source, offset),
+        None => parse_metadata(md_str, None, &metadata_str, metadata_offset),
+    }
+    .map_or_else(|e| (None, Some(e)), |m| (Some(m), None));
+    let ids = heading_ids(&headings);
+    let mut toc_entries = Vec::new();
+    for ((level, _, text), id) in headings.into_iter().zip(ids.iter().cloned()) {
+        match level {
+            HeadingLevel::H1 if title.is_empty() => title = text,
+            HeadingLevel::H1 => (),
+            level => toc_entries.push(TocEntry {
+                level: level as u8,
+                id,
+                title: text,
+                children: Vec::new(),
+            }),
+        }
+    }
+    let toc = if metadata.as_ref().and_then(|m| m.toc) == Some(false) {
+        Vec::new()
+    } else {
+        nest_toc(toc_entries)
+    };
+    parser = Parser::new_ext(body, options);
+    let mut code_lang: Option<String> = None;
+    let mut code = String::new();
+    let mut ids = ids.into_iter();
+    let mut heading_id = String::new();
+    let events = parser.flat_map(|event| match event {
+        Event::Start(Tag::Heading {
+            level,
+            classes,
+            attrs,
+            ..
+        }) => {
+            heading_id = ids.next().unwrap_or_default();
+            vec![Event::Start(Tag::Heading {
+                level,
+                id: Some(heading_id.clone().into()),
+                classes,
+                attrs,
+            })]
+        }
+        Event::End(TagEnd::Heading(_)) if heading_anchors => vec![
+            Event::InlineHtml(
+                format!(
+                    r##" <a class="heading-anchor" href="#{}">#</a>"##,
+                    escape_xml(&heading_id)
+                )
+                .into(),
+            ),
+            event,
+        ],
+        _ => vec![event],
+    });
+    let events = events.filter_map(|event| {
+        let Some(highlight) = highlight else {
+            return Some(event);
+        };
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                // the info string may contain more than the language i.e. "rust,ignore"
+                let lang = info
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .next()
+                    .unwrap_or_default();
+                if !is_supported(lang) {
+                    return Some(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))));
+                }
+                code_lang = Some(lang.to_string());
+                code.clear();
+                None
+            }
+            Event::Text(text) if code_lang.is_some() => {
+                code.push_str(&text);
+                None
+            }
+            Event::End(TagEnd::CodeBlock) if code_lang.is_some() => {
+                let lang = code_lang.take().unwrap_or_default();
+                Some(Event::Html(highlight_code(&code, &lang, highlight).into()))
+            }
+            _ => Some(event),
+        }
+    });
+    let events = events.collect::<Vec<_>>();
+    let excerpt = excerpt(&events);
+    let mut html_str = String::new();
+    html::push_html(&mut html_str, events.into_iter());
+    Document {
+        metadata,
+        metadata_error,
+        title,
+        contents: html_str,
+        toc,
+        excerpt,
+        word_count: plain_text.split_whitespace().count(),
+    }
+}
+
+/// Renders the content before a `<!--more-->` marker or else the first paragraph, leaving out
+/// the metadata and the title.
+fn excerpt(events: &[Event]) -> Option<String> {
+    let mut body = Vec::new();
+    let mut skip_until = None;
+    let mut has_title = false;
+    for (i, event) in events.iter().enumerate() {
+        if let Some(end) = &skip_until {
+            if matches!(event, Event::End(e) if e == end) {
+                skip_until = None;
+            }
+            continue;
+        }
+        match event {
+            Event::Start(Tag::HtmlBlock) if matches!(events.get(i + 1), Some(Event::Html(html)) if html.trim() == "<!--metadata") =>
+            {
+                skip_until = Some(TagEnd::HtmlBlock);
+            }
+            Event::Start(Tag::Heading {
+                level: HeadingLevel::H1,
+                ..
+            }) if !has_title => {
+                has_title = true;
+                skip_until = Some(TagEnd::Heading(HeadingLevel::H1));
+            }
+            event => body.push(event.clone()),
+        }
+    }
+    let is_marker = |event: &Event| matches!(event, Event::Html(html) | Event::InlineHtml(html) if html.trim() == "<!--more-->");
+    let excerpt = match body.iter().position(is_marker) {
+        Some(end) => {
+            let mut excerpt = body[..end].to_vec();
+            // the marker may be inside of a paragraph which has to be closed
+            let mut open = Vec::new();
+            for event in &excerpt {
+                match event {
+                    Event::Start(tag) => open.push(tag.to_end()),
+                    Event::End(_) => {
+                        open.pop();
+                    }
+                    _ => (),
+                }
+            }
+            excerpt.extend(open.into_iter().rev().map(Event::End));
+            excerpt
+        }
+        None => {
+            let start = body
+                .iter()
+                .position(|e| matches!(e, Event::Start(Tag::Paragraph)))?;
+            let len = body[start..]
+                .iter()
+                .position(|e| matches!(e, Event::End(TagEnd::Paragraph)))?;
+            body[start..=start + len].to_vec()
+        }
+    };
     let mut html_str = String::new();
-    html::push_html(&mut html_str, parser);
-    (metadata, title, html_str)
+    html::push_html(&mut html_str, excerpt.into_iter());
+    Some(html_str.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+/// Joins the components of a relative path with `/` regardless of the platform.
+pub fn url_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Inserts a hash of `contents` before the extension i.e. `style.css` becomes `style.1a2b3c4d.css`.
+pub fn fingerprint_path(path: &Path, contents: &[u8]) -> PathBuf {
+    let hash = blake3::hash(contents).to_hex();
+    let hash = &hash[..8];
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}.{hash}.{}", extension.to_string_lossy()),
+        None => format!("{stem}.{hash}"),
+    };
+    path.with_file_name(name)
+}
+
+pub fn preserve_globs(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        // a trailing slash marks a dir but the paths matched against have none
+        let pattern = pattern.trim_end_matches('/');
+        builder.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+    }
+    builder.build()
+}
+
+/// Checks that `collection` is written to a dir of its own, one that is not the root, the assets,
+/// a taxonomy or inside the dir of another collection.
+pub fn check_collection_path(
+    site: &SiteConfig,
+    collection: &Collection,
+    collections: &[Collection],
+) -> Result<(), String> {
+    let path = Path::new(&collection.path);
+    if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(format!(
+            "Collection path \"{}\" must be a relative path without \".\" or \"..\"",
+            collection.path
+        ));
+    }
+    let reserved = site.taxonomies.iter().map(String::as_str).chain(["assets"]);
+    for name in reserved {
+        if path.starts_with(name) {
+            return Err(format!(
+                "Collection path \"{}\" overlaps \"{name}\"",
+                collection.path
+            ));
+        }
+    }
+    for other in collections.iter().filter(|c| c.name != collection.name) {
+        let other_path = Path::new(&other.path);
+        if path.starts_with(other_path) || other_path.starts_with(path) {
+            return Err(format!(
+                "Collection path \"{}\" overlaps the path of collection \"{}\"",
+                collection.path, other.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+pub fn reading_time(word_count: usize) -> usize {
+    word_count.div_ceil(WORDS_PER_MINUTE).max(1)
 }
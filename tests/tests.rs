@@ -1,5 +1,23 @@
-use stoic::types::EntityMetadata;
-use stoic::utils::md_to_html;
+use handlebars::Handlebars;
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use stoic::changes::{classify_change, Change};
+use stoic::errors::MetadataError;
+use stoic::feeds::{atom_feed, rss_feed};
+use stoic::handlers::build_output;
+use stoic::helpers::{register_helpers, Asset};
+use stoic::links::{broken_links, resolve_link};
+use stoic::minify::{minify_asset, minify_css, minify_html, minify_js};
+use stoic::sitemap::{sitemap, SitemapEntry};
+use stoic::types::{
+    BuildOptions, Collection, CollectionConfig, Document, Entity, EntityMetadata, HighlightConfig,
+    Pagination, SiteConfig, TocEntry,
+};
+use stoic::utils::{
+    check_collection_path, entity_filename, escape_xml, fingerprint_path, md_to_html,
+    neighbour_sources, neighbours, paginate, preserve_globs, read_site_config, term_slug,
+};
 use toml_datetime::{Date, Datetime};
 
 const TEST_MD: &str = r#"
@@ -18,7 +36,12 @@ mod tests {
 
     #[test]
     fn md_to_html_works() {
-        let (metadata, title, contents) = md_to_html(TEST_MD);
+        let Document {
+            metadata,
+            title,
+            contents,
+            ..
+        } = md_to_html(TEST_MD, None, false);
         assert!(matches!(
             metadata,
             Some(EntityMetadata {
@@ -34,6 +57,7 @@ mod tests {
                     time: None,
                     offset: None
                 }),
+                ..
             }) if shortname == "title" && slug == " hey there " && head_title == "head title"
         ));
         assert_eq!(title, "Title");
@@ -45,8 +69,678 @@ shortname = "title"
 slug = " hey there "
 head_title = "head title"
 -->
-<h1>Title</h1>
+<h1 id="title">Title</h1>
 "#
         );
     }
+
+    #[test]
+    fn md_to_html_keeps_extra_metadata() {
+        let md = "<!--metadata\nsubtitle = \"subtitle\"\nrating = 5\n-->\n";
+        let Document { metadata, .. } = md_to_html(md, None, false);
+        let extra = metadata.unwrap().extra;
+        assert_eq!(
+            extra.get("subtitle").and_then(|s| s.as_str()),
+            Some("subtitle")
+        );
+        assert_eq!(extra.get("rating").and_then(|r| r.as_integer()), Some(5));
+    }
+
+    #[test]
+    fn md_to_html_parses_terms() {
+        let md = "<!--metadata\ntags = [\"a\", \"b\"]\n\n[taxonomies]\ncategories = [\"c\"]\n-->\n";
+        let Document { metadata, .. } = md_to_html(md, None, false);
+        let metadata = metadata.unwrap();
+        assert_eq!(metadata.terms("tags"), ["a", "b"]);
+        assert_eq!(metadata.terms("categories"), ["c"]);
+        assert!(metadata.terms("series").is_empty());
+    }
+
+    fn entity(filename: &str, title: &str) -> Entity {
+        Entity {
+            source: filename.replace(".html", ".md"),
+            filename: filename.to_string(),
+            link: format!("posts/{filename}"),
+            title: title.to_string(),
+            created_at_iso: "2023-03-24".to_string(),
+            created_at: "Mar 24, 2023".to_string(),
+            shortname: title.to_string(),
+            head_title: title.to_string(),
+            contents: "<h1>Hello</h1>".to_string(),
+            toc: Vec::new(),
+            taxonomies: Default::default(),
+            draft: false,
+            scheduled: false,
+            meta: Default::default(),
+            excerpt: None,
+            word_count: 1,
+            reading_time: 1,
+        }
+    }
+
+    #[test]
+    fn feeds_work() {
+        let site = SiteConfig {
+            title: Some("Blog".to_string()),
+            ..SiteConfig::default()
+        };
+        let entities = [entity("hello.html", "Hello & Goodbye")];
+        let rss = rss_feed(&site, "https://example.com/", "posts", "Posts", &entities);
+        assert!(rss.contains("<title>Blog - Posts</title>"));
+        assert!(rss.contains("<link>https://example.com/posts/hello.html</link>"));
+        assert!(rss.contains("<title>Hello &amp; Goodbye</title>"));
+        assert!(rss.contains("<pubDate>Fri, 24 Mar 2023 00:00:00 +0000</pubDate>"));
+        assert!(rss.contains("<description>&lt;h1&gt;Hello&lt;/h1&gt;</description>"));
+        let atom = atom_feed(&site, "https://example.com", "posts", "Posts", &entities);
+        assert!(atom.contains(r#"<link href="https://example.com/posts/atom.xml" rel="self"/>"#));
+        assert!(atom.contains("<updated>2023-03-24T00:00:00+00:00</updated>"));
+        // the newest entity dates the feed in any collection order
+        let mut newer = entity("newer.html", "Newer");
+        newer.created_at_iso = "2023-04-01".to_string();
+        let entities = [entity("hello.html", "Hello"), newer];
+        let rss = rss_feed(&site, "https://example.com", "posts", "Posts", &entities);
+        assert!(rss.contains("<lastBuildDate>Sat, 1 Apr 2023 00:00:00 +0000</lastBuildDate>"));
+        let atom = atom_feed(&site, "https://example.com", "posts", "Posts", &entities);
+        assert!(atom.contains("<updated>2023-04-01T00:00:00+00:00</updated>"));
+
+        // links relative to the site root are made absolute for feed readers
+        let mut linking = entity("linking.html", "Linking");
+        linking.contents = r##"<a href="posts/b.html">b</a><img src="/assets/pic.png"><a href="#fn1">1</a><a href="https://x.org/">x</a>"##.to_string();
+        let expected = escape_xml(
+            r##"<a href="https://example.com/posts/b.html">b</a><img src="https://example.com/assets/pic.png"><a href="#fn1">1</a><a href="https://x.org/">x</a>"##,
+        );
+        let entities = [linking];
+        let rss = rss_feed(&site, "https://example.com/", "posts", "Posts", &entities);
+        assert!(rss.contains(&expected));
+        let atom = atom_feed(&site, "https://example.com/", "posts", "Posts", &entities);
+        assert!(atom.contains(&expected));
+    }
+
+    #[test]
+    fn sitemap_works() {
+        let entries = [
+            SitemapEntry {
+                path: "index.html".to_string(),
+                lastmod: None,
+            },
+            SitemapEntry {
+                path: "posts/hello.html".to_string(),
+                lastmod: Some("2023-03-24".to_string()),
+            },
+        ];
+        let out = sitemap("https://example.com/", &entries);
+        assert!(out.contains("<url><loc>https://example.com/</loc></url>"));
+        assert!(out.contains(
+            "<url><loc>https://example.com/posts/hello.html</loc><lastmod>2023-03-24</lastmod></url>"
+        ));
+    }
+
+    #[test]
+    fn paginate_works() {
+        let entities = ["a", "b", "c"].map(|n| entity(&format!("{n}.html"), n));
+        let pages = paginate(&entities, Some(2), "posts");
+        assert_eq!(pages.len(), 2);
+        assert_eq!(
+            pages[0].0,
+            Pagination {
+                current: 1,
+                total: 2,
+                previous: None,
+                next: Some("posts/page/2/".to_string()),
+            }
+        );
+        assert_eq!(pages[0].1.len(), 2);
+        assert_eq!(pages[1].0.previous, Some("posts/".to_string()));
+        assert_eq!(pages[1].1.len(), 1);
+        assert_eq!(paginate(&entities, None, "posts").len(), 1);
+        assert_eq!(paginate(&[], Some(2), "posts").len(), 1);
+    }
+
+    #[test]
+    fn classify_change_works() {
+        let change = |p: &str| classify_change(Path::new(p));
+        assert_eq!(change("stoic.toml"), Change::Full);
+        assert_eq!(change("templates"), Change::Full);
+        assert_eq!(change("themes/shared/templates/nav.hbs"), Change::Full);
+        assert_eq!(
+            change("assets/img/a.png"),
+            Change::Asset(PathBuf::from("img/a.png"))
+        );
+        assert_eq!(
+            change("templates/post.hbs"),
+            Change::Template("post".to_string())
+        );
+        assert_eq!(change("about.md"), Change::Page("about.md".to_string()));
+        assert_eq!(change("README.md"), Change::Ignored);
+        assert_eq!(change("works"), Change::Root("works".to_string()));
+        assert_eq!(
+            change("posts/hello.md"),
+            Change::Entity("posts".to_string(), "hello.md".to_string())
+        );
+        assert_eq!(change("posts/_collection.toml"), Change::Full);
+        assert_eq!(change("posts/.hello.md.swp"), Change::Ignored);
+        assert_eq!(change(".git/index"), Change::Ignored);
+    }
+
+    #[test]
+    fn md_to_html_highlights_code() {
+        let md = "```rust\nfn main() {}\n```\n\n```unknown\n<a>\n```\n";
+        let config = HighlightConfig {
+            line_numbers: true,
+            ..HighlightConfig::default()
+        };
+        let Document { contents, .. } = md_to_html(md, Some(&config), false);
+        assert!(contents.starts_with(
+            r#"<pre class="highlight hl-code"><code class="language-rust"><span class="line-number">1</span><span class="hl-source hl-rust">"#
+        ));
+        assert!(
+            contents.contains(r#"<span class="hl-storage hl-type hl-function hl-rust">fn</span>"#)
+        );
+        assert!(
+            contents.ends_with("<pre><code class=\"language-unknown\">&lt;a&gt;\n</code></pre>\n")
+        );
+        let Document { contents, .. } = md_to_html(md, None, false);
+        assert!(contents.starts_with("<pre><code class=\"language-rust\">fn main() {}"));
+    }
+
+    #[test]
+    fn md_to_html_parses_front_matter() {
+        let yaml = "---\ndate: 2023-03-24\nslug: hey\ntags:\n  - a\n---\n# Title\n";
+        let toml = "+++\ndate = 2023-03-24\nslug = \"hey\"\ntags = [\"a\"]\n+++\n# Title\n";
+        for md in [yaml, toml] {
+            let Document {
+                metadata,
+                title,
+                contents,
+                ..
+            } = md_to_html(md, None, false);
+            assert!(matches!(
+                metadata,
+                Some(EntityMetadata {
+                    slug: Some(slug),
+                    tags: Some(tags),
+                    date: Some(Datetime {
+                        date: Some(Date {
+                            year: 2023,
+                            month: 3,
+                            day: 24
+                        }),
+                        ..
+                    }),
+                    ..
+                }) if slug == "hey" && tags == ["a"]
+            ));
+            assert_eq!(title, "Title");
+            assert_eq!(contents, "<h1 id=\"title\">Title</h1>\n");
+        }
+
+        // `---` pairs below the top are horizontal rules, not front matter
+        for section in ["Section two is here", "note: this is important"] {
+            let md = format!("# T\n\nIntro\n\n---\n{section}\n---\n\nOutro\n");
+            let Document {
+                metadata,
+                metadata_error,
+                contents,
+                ..
+            } = md_to_html(&md, None, false);
+            assert!(metadata.is_some_and(|m| m.extra.is_empty()));
+            assert!(metadata_error.is_none());
+            assert!(contents.contains(section));
+            assert!(contents.contains("<p>Outro</p>"));
+        }
+    }
+
+    #[test]
+    fn md_to_html_builds_toc() {
+        let md = "# Title
+## A
+### A.1 `code`
+#### A.1.a
+## B {#bee}
+### B.1
+";
+        let Document { toc, .. } = md_to_html(md, None, false);
+        let entry = |level, id: &str, title: &str, children| TocEntry {
+            level,
+            id: id.to_string(),
+            title: title.to_string(),
+            children,
+        };
+        assert_eq!(
+            toc,
+            vec![
+                entry(
+                    2,
+                    "a",
+                    "A",
+                    vec![entry(
+                        3,
+                        "a-1-code",
+                        "A.1 code",
+                        vec![entry(4, "a-1-a", "A.1.a", vec![])]
+                    )]
+                ),
+                entry(2, "bee", "B", vec![entry(3, "b-1", "B.1", vec![])]),
+            ]
+        );
+        let Document { toc, .. } = md_to_html(&format!("+++\ntoc = false\n+++\n{md}"), None, false);
+        assert!(toc.is_empty());
+    }
+
+    #[test]
+    fn md_to_html_generates_heading_ids() {
+        let md = "## Intro\n## Intro\n## Intro {#intro-1}\n## ?\n";
+        let Document { contents, .. } = md_to_html(md, None, false);
+        assert_eq!(
+            contents,
+            "<h2 id=\"intro\">Intro</h2>\n<h2 id=\"intro-2\">Intro</h2>\n<h2 id=\"intro-1\">Intro</h2>\n<h2 id=\"section\">?</h2>\n"
+        );
+        let Document { contents, .. } = md_to_html("## Intro\n", None, true);
+        assert_eq!(
+            contents,
+            "<h2 id=\"intro\">Intro <a class=\"heading-anchor\" href=\"#intro\">#</a></h2>\n"
+        );
+    }
+
+    #[test]
+    fn md_to_html_extracts_excerpt() {
+        let md = "<!--metadata\nslug = \"a\"\n-->\n# Title\n\nFirst *one*.\n\nSecond one.\n";
+        let Document {
+            excerpt,
+            word_count,
+            ..
+        } = md_to_html(md, None, false);
+        assert_eq!(excerpt.as_deref(), Some("<p>First <em>one</em>.</p>"));
+        assert_eq!(word_count, 5);
+        let md = "# Title\n\nFirst.\n\n- Second<!--more--> third\n";
+        let Document { excerpt, .. } = md_to_html(md, None, false);
+        assert_eq!(
+            excerpt.as_deref(),
+            Some("<p>First.</p>\n<ul>\n<li>Second</li>\n</ul>")
+        );
+        let Document { excerpt, .. } = md_to_html("# Title\n", None, false);
+        assert_eq!(excerpt, None);
+    }
+
+    #[test]
+    fn collection_config_works() {
+        let collection = Collection::new("news", CollectionConfig::default());
+        assert_eq!(collection.title, "News");
+        assert_eq!(collection.item_template, "new");
+        assert_eq!(collection.index_template, "news");
+        assert_eq!(collection.path, "news");
+        let config = toml::from_str(
+            r#"
+            title = "Latest News"
+            item_template = "article"
+            sort_by = "title"
+            sort_order = "asc"
+            path = "/blog/news/"
+            render_index = false
+            "#,
+        )
+        .unwrap();
+        let collection = Collection::new("news", config);
+        assert_eq!(collection.title, "Latest News");
+        assert_eq!(collection.item_template, "article");
+        assert_eq!(collection.path, "blog/news");
+        assert!(!collection.render_index);
+        let mut entities = vec![entity("b.html", "B"), entity("a.html", "A")];
+        collection.sort(&mut entities);
+        assert_eq!(entities[0].title, "A");
+    }
+
+    #[test]
+    fn neighbours_work() {
+        let dated = |name: &str, date: &str| {
+            let mut entity = entity(&format!("{name}.html"), name);
+            entity.created_at_iso = date.to_string();
+            entity
+        };
+        fn titles<'a>(
+            (previous, next): (Option<&'a Entity>, Option<&'a Entity>),
+        ) -> (Option<&'a str>, Option<&'a str>) {
+            (
+                previous.map(|e| e.title.as_str()),
+                next.map(|e| e.title.as_str()),
+            )
+        }
+        let mut entities = vec![
+            dated("A", "2023-03-01"),
+            dated("B", "2023-03-02"),
+            dated("C", "2023-03-03"),
+        ];
+        // previous is older and next is newer in either order
+        for sort_order in ["desc", "asc"] {
+            let config = toml::from_str(&format!("sort_order = {sort_order:?}")).unwrap();
+            Collection::new("posts", config).sort(&mut entities);
+            let i = entities.iter().position(|e| e.title == "B").unwrap();
+            assert_eq!(titles(neighbours(&entities, i)), (Some("A"), Some("C")));
+            let i = entities.iter().position(|e| e.title == "A").unwrap();
+            assert_eq!(titles(neighbours(&entities, i)), (None, Some("B")));
+        }
+        let config = toml::from_str(r#"sort_by = "title""#).unwrap();
+        Collection::new("posts", config).sort(&mut entities);
+        assert_eq!(entities[0].title, "C");
+        assert_eq!(titles(neighbours(&entities, 0)), (Some("B"), None));
+
+        // an entity moved by a date change invalidates its old and new neighbours
+        assert_eq!(neighbour_sources(&entities, "B.md"), ["A.md", "C.md"]);
+        let i = entities.iter().position(|e| e.title == "B").unwrap();
+        entities[i].created_at_iso = "2023-03-04".to_string();
+        assert_eq!(neighbour_sources(&entities, "B.md"), ["C.md"]);
+        assert!(neighbour_sources(&entities, "D.md").is_empty());
+    }
+
+    #[test]
+    fn taxonomy_terms_work() {
+        assert_eq!(term_slug("Rust Lang"), "rust-lang");
+        let slug = term_slug("!!!");
+        assert!(slug.starts_with("term-") && slug.len() == 13);
+        assert_eq!(slug, term_slug("!!!"));
+        assert_ne!(slug, term_slug("???"));
+
+        let mut site = SiteConfig::default();
+        assert_eq!(site.term_template("tags"), "tag");
+        site.term_templates
+            .insert("categories".to_string(), "category".to_string());
+        assert_eq!(site.term_template("categories"), "category");
+    }
+
+    #[test]
+    fn check_collection_path_works() {
+        let collection = |name: &str, path: &str| {
+            Collection::new(name, toml::from_str(&format!("path = {path:?}")).unwrap())
+        };
+        let site = SiteConfig::default();
+        let collections = [collection("posts", "blog"), collection("notes", "notes")];
+        for c in &collections {
+            assert!(check_collection_path(&site, c, &collections).is_ok());
+        }
+        for path in [".", "..", "../posts", "blog/../..", "assets", "tags/rust"] {
+            let c = collection("posts", path);
+            assert!(check_collection_path(&site, &c, &[]).is_err(), "{path}");
+        }
+        let collections = [
+            collection("posts", "blog"),
+            collection("notes", "blog/notes"),
+        ];
+        assert!(check_collection_path(&site, &collections[0], &collections).is_err());
+        assert!(check_collection_path(&site, &collections[1], &collections).is_err());
+    }
+
+    #[tokio::test]
+    async fn build_output_works() {
+        let dir = std::env::temp_dir().join("stoic-build-output-works");
+        let _ = fs::remove_dir_all(&dir);
+        let (site_dir, output_dir) = (dir.join("site"), dir.join("out"));
+        fs::create_dir_all(&site_dir).unwrap();
+        fs::write(site_dir.join("index.md"), "# Home\n").unwrap();
+        fs::create_dir_all(output_dir.join(".git")).unwrap();
+        fs::write(output_dir.join(".git/HEAD"), "ref").unwrap();
+        fs::write(output_dir.join("stale.html"), "").unwrap();
+        let build = |clean: bool| {
+            let (site_dir, output_dir) = (site_dir.clone(), output_dir.clone());
+            async move {
+                let site = read_site_config(&site_dir).await.unwrap();
+                let options = BuildOptions {
+                    clean,
+                    ..BuildOptions::default()
+                };
+                build_output(&site_dir, &output_dir, site, &options, &Default::default()).await
+            }
+        };
+        let staging_dir = dir.join(".out.staging");
+
+        // preserved files are kept and stale ones removed, whether swapped or synced
+        for clean in [true, false] {
+            build(clean).await.unwrap();
+            assert!(output_dir.join("index.html").is_file());
+            assert!(output_dir.join(".git/HEAD").is_file());
+            assert!(!output_dir.join("stale.html").exists());
+            assert!(!staging_dir.exists());
+            fs::write(output_dir.join("stale.html"), "").unwrap();
+        }
+
+        // a failed build leaves the output as is
+        fs::create_dir_all(site_dir.join("templates")).unwrap();
+        fs::write(site_dir.join("templates/index.hbs"), "{{> missing}}").unwrap();
+        assert!(build(true).await.is_err());
+        assert!(output_dir.join("index.html").is_file());
+        assert!(output_dir.join("stale.html").is_file());
+        assert!(output_dir.join(".git/HEAD").is_file());
+        assert!(!staging_dir.exists());
+        fs::remove_dir_all(site_dir.join("templates")).unwrap();
+
+        // a symlinked output dir is written through
+        #[cfg(unix)]
+        {
+            let real_dir = dir.join("real");
+            fs::rename(&output_dir, &real_dir).unwrap();
+            std::os::unix::fs::symlink(&real_dir, &output_dir).unwrap();
+            build(true).await.unwrap();
+            assert!(fs::symlink_metadata(&output_dir).unwrap().is_symlink());
+            assert!(real_dir.join("index.html").is_file());
+            assert!(real_dir.join(".git/HEAD").is_file());
+            assert!(!real_dir.join("stale.html").exists());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn md_to_html_reports_metadata_errors() {
+        let md = "<!--metadata\ndate = 2023-03-25\nslug = \"foo_bar\n-->\n# Title\n";
+        let Document {
+            metadata,
+            metadata_error,
+            ..
+        } = md_to_html(md, None, false);
+        assert!(metadata.is_none());
+        assert!(matches!(
+            metadata_error,
+            Some(MetadataError {
+                line: 3,
+                column: 16,
+                ..
+            })
+        ));
+        let yaml = "---\ndate: 2023-03-25\ntags: [a\n---\n# Title\n";
+        let Document { metadata_error, .. } = md_to_html(yaml, None, false);
+        assert!(matches!(
+            metadata_error,
+            Some(MetadataError { line: 4, .. })
+        ));
+        // type errors point at the value in the yaml, not the converted toml
+        let yaml = "---\ndate: 2023-01-03\ntitle_x: a\nshortname: s\ndraft: maybe\n---\n";
+        let Document { metadata_error, .. } = md_to_html(yaml, None, false);
+        assert!(matches!(
+            metadata_error,
+            Some(MetadataError {
+                line: 5,
+                column: 8,
+                ..
+            })
+        ));
+        let yaml = "---\ntags: [a]\ntaxonomies:\n  categories: 1\n---\n";
+        let Document { metadata_error, .. } = md_to_html(yaml, None, false);
+        assert!(matches!(
+            metadata_error,
+            Some(MetadataError {
+                line: 4,
+                column: 15,
+                ..
+            })
+        ));
+        let yaml = "---\n- a\n---\n# Title\n";
+        let Document {
+            metadata,
+            metadata_error,
+            ..
+        } = md_to_html(yaml, None, false);
+        assert!(metadata.is_none());
+        assert!(matches!(
+            metadata_error,
+            Some(MetadataError { line: 2, .. })
+        ));
+        let Document { metadata_error, .. } = md_to_html(TEST_MD, None, false);
+        assert!(metadata_error.is_none());
+    }
+
+    #[test]
+    fn entity_filename_works() {
+        assert_eq!(
+            entity_filename("2023-03-25", "Hello, World!").as_deref(),
+            Some("2023-03-25-hello-world.md")
+        );
+        assert_eq!(entity_filename("2023-03-25", "?!"), None);
+    }
+
+    #[test]
+    fn preserve_globs_work() {
+        let preserve = preserve_globs(&[
+            ".git".to_string(),
+            "downloads/".to_string(),
+            "*.txt".to_string(),
+        ])
+        .unwrap();
+        assert!(preserve.is_match(".git"));
+        assert!(preserve.is_match("downloads"));
+        assert!(preserve.is_match("keybase.txt"));
+        assert!(!preserve.is_match("posts/notes.txt"));
+        assert!(!preserve.is_match("CNAME"));
+        assert!(!preserve.is_match("index.html"));
+        assert!(preserve_globs(&["[".to_string()]).is_err());
+        let site = SiteConfig {
+            preserve: vec![".nojekyll".to_string()],
+            ..SiteConfig::default()
+        };
+        let preserve = preserve_globs(&site.preserve_patterns()).unwrap();
+        assert!(preserve.is_match(".git"));
+        assert!(preserve.is_match("CNAME"));
+        assert!(preserve.is_match(".nojekyll"));
+    }
+
+    #[test]
+    fn resolve_link_works() {
+        assert_eq!(
+            resolve_link("posts/a.html", None, "b.html").as_deref(),
+            Some("posts/b.html")
+        );
+        assert_eq!(
+            resolve_link("posts/a.html", Some("/"), "posts/b.html?x=1#top").as_deref(),
+            Some("posts/b.html")
+        );
+        assert_eq!(
+            resolve_link("posts/a.html", None, "/tags/").as_deref(),
+            Some("tags/index.html")
+        );
+        assert_eq!(
+            resolve_link("posts/a.html", None, "../my%20file.pdf").as_deref(),
+            Some("my file.pdf")
+        );
+        assert_eq!(resolve_link("posts/a.html", None, "#top"), None);
+        assert_eq!(resolve_link("posts/a.html", None, "https://a.com"), None);
+        assert_eq!(resolve_link("posts/a.html", None, "mailto:a@b.c"), None);
+        assert_eq!(resolve_link("posts/a.html", None, "//a.com/b"), None);
+    }
+
+    #[test]
+    fn broken_links_works() {
+        let html = r#"<base href="/"><!-- <a href="gone.html"> -->
+<a href="posts/a.html">a</a> <a class=x href=posts/b.html>b</a> <img src='c.png' alt="·">
+<script>let s = "<a href='gone.html'>";</script><a href="posts/a.html?page=1&amp;x">a</a>"#;
+        let exists = |path: &str| path == "posts/a.html";
+        assert_eq!(
+            broken_links("posts/a.html", html, exists),
+            vec!["posts/b.html", "c.png"]
+        );
+    }
+
+    #[test]
+    fn helpers_work() {
+        let mut h = Handlebars::new();
+        register_helpers(&mut h);
+        let data = json!({
+            "site": { "base_url": "https://example.com/" },
+            "date": "2023-03-25",
+            "title": "Hello, World!",
+            "items": [1, 2, 3],
+        });
+        let render = |template: &str| h.render_template(template, &data).unwrap();
+        assert_eq!(
+            render(r#"{{date_format date "%B %e, %Y"}}"#),
+            "March 25, 2023"
+        );
+        assert_eq!(render("{{slugify title}}"), "hello-world");
+        assert_eq!(render("{{truncate title 5}}"), "Hello…");
+        assert_eq!(
+            render(r#"{{truncate title 6 ellipsis="..."}}"#),
+            "Hello,..."
+        );
+        assert_eq!(render("{{truncate title 20}}"), "Hello, World!");
+        assert_eq!(render("{{{markdownify \"*hi*\"}}}"), "<p><em>hi</em></p>\n");
+        assert_eq!(render("{{{json items}}}"), "[1,2,3]");
+        assert_eq!(
+            render("{{absolute_url \"/posts/a.html\"}}"),
+            "https://example.com/posts/a.html"
+        );
+        assert_eq!(render("{{#each (limit items 2)}}{{this}}{{/each}}"), "12");
+        assert_eq!(render("{{#if (gt date \"2023-01-01\")}}yes{{/if}}"), "yes");
+        assert!(h
+            .render_template(r#"{{date_format date "%Q"}}"#, &data)
+            .is_err());
+    }
+
+    #[test]
+    fn fingerprint_assets_works() {
+        let path = fingerprint_path(Path::new("css/style.css"), b"body {}");
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        assert_eq!(path.parent(), Some(Path::new("css")));
+        assert!(name.starts_with("style.") && name.ends_with(".css") && name.len() == 18);
+        assert_eq!(
+            path,
+            fingerprint_path(Path::new("css/style.css"), b"body {}")
+        );
+        assert_ne!(
+            path,
+            fingerprint_path(Path::new("css/style.css"), b"body { }")
+        );
+
+        let mut h = Handlebars::new();
+        h.register_helper(
+            "asset",
+            Box::new(Asset(
+                [("style.css".to_string(), "style.1a2b3c4d.css".to_string())].into(),
+            )),
+        );
+        let render = |template: &str| h.render_template(template, &json!({})).unwrap();
+        assert_eq!(
+            render(r#"{{asset "style.css"}}"#),
+            "assets/style.1a2b3c4d.css"
+        );
+        assert_eq!(render(r#"{{asset "script.js"}}"#), "assets/script.js");
+    }
+
+    #[test]
+    fn minify_works() {
+        assert_eq!(
+            minify_html("<p>\n  <a  href=\"posts/a.html\">a</a>\n</p>\n<!-- comment -->"),
+            "<p><a href=posts/a.html>a</a>"
+        );
+        assert_eq!(
+            minify_css("body {\n  color: #ff0000;\n  margin: 0px;\n}\n").unwrap(),
+            "body{color:red;margin:0}"
+        );
+        let js = b"function add(first, second) {\n  return first + second;\n}\n";
+        let minified = String::from_utf8(minify_js(js).unwrap()).unwrap();
+        // top level names are kept for other scripts
+        assert!(minified.contains("add") && !minified.contains("first"));
+        assert!(minified.len() < js.len());
+        assert!(minify_css("body {").is_ok());
+        assert!(minify_js(b"function (").is_err());
+        assert_eq!(
+            minify_asset(Path::new("a.png"), vec![1, 2]).unwrap(),
+            vec![1, 2]
+        );
+    }
 }